@@ -0,0 +1,336 @@
+use std::{
+    io::{self, BufRead, BufReader, Read},
+    net::TcpStream,
+    time::{Duration, Instant},
+};
+
+use crate::{Limits, Request, Response, Router, Shutdown, Version};
+
+/// `KeepAlive` struct and implementations
+///
+/// How long a connection is kept open for more requests, so a client can't hold on to a worker
+/// forever.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeepAlive {
+    /// How long to wait for the client to send anything before closing the connection, and how
+    /// long it has to send the whole of a request once it has started.
+    pub idle_timeout: Duration,
+    /// How many requests to answer before closing the connection.
+    pub max_requests: usize,
+}
+
+impl Default for KeepAlive {
+    fn default() -> KeepAlive {
+        KeepAlive {
+            idle_timeout: Duration::from_secs(5),
+            max_requests: 100,
+        }
+    }
+}
+
+/// How often a connection waiting for its next request looks for a shutdown.
+const SHUTDOWN_POLL: Duration = Duration::from_millis(100);
+
+/// Answer the requests sent over `stream` with `router`, one after the other, until the client
+/// asks to close the connection, goes quiet for longer than the idle timeout, or reaches the
+/// maximum number of requests. Requests sent without waiting for the responses before them are
+/// answered in order.
+///
+/// Once `shutdown` is requested, the request being answered is the last one, and a connection
+/// waiting for its next request is closed. The first request of a connection is still answered,
+/// since the connection was accepted before the shutdown.
+///
+/// Returns the number of requests answered.
+pub fn serve_connection(
+    stream: TcpStream,
+    router: &Router,
+    limits: &Limits,
+    keep_alive: &KeepAlive,
+    shutdown: &Shutdown,
+) -> io::Result<usize> {
+    stream.set_write_timeout(Some(keep_alive.idle_timeout))?;
+
+    // pipelined requests wait in the buffer until their turn comes
+    let mut reader = BufReader::new(Deadline {
+        stream: &stream,
+        deadline: Instant::now(),
+    });
+    let mut writer = &stream;
+    let mut served = 0;
+
+    while served < keep_alive.max_requests {
+        // closing or going quiet between requests is how a connection is meant to end
+        let idle = Instant::now();
+        loop {
+            if served > 0 && shutdown.is_requested() {
+                return Ok(served);
+            }
+
+            let left = keep_alive.idle_timeout.saturating_sub(idle.elapsed());
+            if left.is_zero() {
+                return Ok(served);
+            }
+            reader.get_mut().deadline = Instant::now() + left.min(SHUTDOWN_POLL);
+
+            match reader.fill_buf() {
+                Ok([]) => return Ok(served),
+                Ok(_) => break,
+                Err(error) if is_timeout(&error) => continue,
+                Err(error) => return Err(error),
+            }
+        }
+
+        // a client sending its request a byte at a time doesn't get to keep the worker any longer
+        reader.get_mut().deadline = Instant::now() + keep_alive.idle_timeout;
+        let request = match Request::read(&mut reader, limits) {
+            Ok(request) => request,
+            Err(error) => {
+                eprintln!("Bad request: {error}");
+
+                // what follows a bad request can't be told apart from the rest of it
+                if let Some(status) = error.status() {
+                    Response::new(status)
+                        .with_header("Connection", "close")
+                        .write_to(&mut writer)?;
+                }
+                break;
+            }
+        };
+
+        served += 1;
+        let mut response = router.handle(&request);
+
+        let close =
+            served == keep_alive.max_requests || wants_close(&request) || shutdown.is_requested();
+        if close {
+            response.headers.append("Connection", "close");
+        } else if request.version == Version::Http10 {
+            response.headers.append("Connection", "keep-alive");
+        }
+        response.write_to(&mut writer)?;
+        shutdown.answered();
+
+        if close {
+            break;
+        }
+    }
+
+    Ok(served)
+}
+
+/// Whether the client wants the connection closed after `request`: HTTP/1.1 connections stay
+/// open unless it says `close`, HTTP/1.0 ones only when it says `keep-alive`.
+fn wants_close(request: &Request) -> bool {
+    let has = |option: &str| {
+        request
+            .headers
+            .get_all("Connection")
+            .flat_map(|value| value.split(','))
+            .any(|token| token.trim().eq_ignore_ascii_case(option))
+    };
+
+    match request.version {
+        Version::Http11 => has("close"),
+        Version::Http10 => !has("keep-alive"),
+    }
+}
+
+/// `Deadline` struct and implementations
+///
+/// Reads from a stream until a deadline, however many reads it takes to get there.
+struct Deadline<'a> {
+    stream: &'a TcpStream,
+    deadline: Instant,
+}
+
+impl Read for Deadline<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let left = self.deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+
+        self.stream.set_read_timeout(Some(left))?;
+        self.stream.read(buf)
+    }
+}
+
+fn is_timeout(error: &io::Error) -> bool {
+    // which of the two a timed out read returns depends on the platform
+    matches!(
+        error.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        io::{Read, Write},
+        net::TcpListener,
+        sync::Arc,
+        thread,
+    };
+
+    /// Connect to a server answering with `keep_alive` and send it `input`, returning what comes
+    /// back until it closes the connection and the number of requests it says it answered.
+    fn exchange(keep_alive: KeepAlive, input: &str) -> (String, usize) {
+        exchange_until(keep_alive, input, Arc::default())
+    }
+
+    /// `exchange`, with a server that also starts `shutdown` when sent `POST /shutdown`.
+    fn exchange_until(
+        keep_alive: KeepAlive,
+        input: &str,
+        shutdown: Arc<Shutdown>,
+    ) -> (String, usize) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let stopper = Arc::clone(&shutdown);
+            let router = Router::new()
+                .get("/:name", |_, params| {
+                    Response::text(200, params.get("name").unwrap().to_string())
+                })
+                .post("/shutdown", move |_, _| {
+                    stopper.request();
+                    Response::new(202)
+                });
+
+            serve_connection(stream, &router, &Limits::default(), &keep_alive, &shutdown).unwrap()
+        });
+
+        let mut client = TcpStream::connect(address).unwrap();
+        client.write_all(input.as_bytes()).unwrap();
+
+        let mut output = String::new();
+        client.read_to_string(&mut output).unwrap();
+
+        (output, server.join().unwrap())
+    }
+
+    fn get(name: &str, headers: &str) -> String {
+        format!("GET /{name} HTTP/1.1\r\nHost: localhost\r\n{headers}\r\n")
+    }
+
+    #[test]
+    fn pipelined_requests_until_close() {
+        let input = get("one", "") + &get("two", "") + &get("three", "Connection: close\r\n");
+        let (output, served) = exchange(KeepAlive::default(), &input);
+
+        assert_eq!(3, served);
+        let bodies: Vec<_> = output
+            .split("HTTP/1.1 200 OK")
+            .skip(1)
+            .map(|response| response.rsplit("\r\n").next().unwrap())
+            .collect();
+        assert_eq!(vec!["one", "two", "three"], bodies);
+        assert_eq!(1, output.matches("Connection: close").count());
+    }
+
+    #[test]
+    fn max_requests_per_connection() {
+        let keep_alive = KeepAlive {
+            max_requests: 2,
+            ..KeepAlive::default()
+        };
+        let input = get("one", "") + &get("two", "") + &get("three", "");
+        let (output, served) = exchange(keep_alive, &input);
+
+        assert_eq!(2, served);
+        assert!(output.ends_with("Connection: close\r\nContent-Length: 3\r\n\r\ntwo"));
+    }
+
+    #[test]
+    fn idle_connections_are_closed() {
+        let keep_alive = KeepAlive {
+            idle_timeout: Duration::from_millis(100),
+            ..KeepAlive::default()
+        };
+        let start = Instant::now();
+        let (output, served) = exchange(keep_alive, &get("one", ""));
+
+        assert_eq!(1, served);
+        assert!(output.ends_with("one"));
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn slow_requests_are_closed() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let keep_alive = KeepAlive {
+                idle_timeout: Duration::from_millis(300),
+                ..KeepAlive::default()
+            };
+            let router = Router::new().get("/:name", |_, _| Response::new(200));
+            let shutdown = Shutdown::default();
+
+            serve_connection(stream, &router, &Limits::default(), &keep_alive, &shutdown).unwrap()
+        });
+
+        // each byte comes well within the timeout, but the request as a whole doesn't
+        let mut client = TcpStream::connect(address).unwrap();
+        for byte in get("one", "").bytes() {
+            thread::sleep(Duration::from_millis(50));
+            if client.write_all(&[byte]).is_err() {
+                break;
+            }
+        }
+
+        assert_eq!(0, server.join().unwrap());
+    }
+
+    #[test]
+    fn http_1_0_and_bad_requests_close() {
+        let (output, served) = exchange(KeepAlive::default(), "GET /old HTTP/1.0\r\n\r\n");
+        assert_eq!(1, served);
+        assert!(output.contains("Connection: close"));
+
+        let input =
+            "GET /old HTTP/1.0\r\nConnection: Keep-Alive\r\n\r\nGET /older HTTP/1.0\r\n\r\n";
+        let (output, served) = exchange(KeepAlive::default(), input);
+        assert_eq!(2, served);
+        assert_eq!(1, output.matches("Connection: keep-alive").count());
+
+        let input = get("one", "Connection: keep-alive\r\n") + "nonsense\r\n\r\n" + &get("two", "");
+        let (output, served) = exchange(KeepAlive::default(), &input);
+        assert_eq!(1, served);
+        assert!(output.ends_with(
+            "HTTP/1.1 400 Bad Request\r\nConnection: close\r\nContent-Length: 0\r\n\r\n"
+        ));
+    }
+
+    #[test]
+    fn shutdown_ends_connections() {
+        let shutdown = Arc::new(Shutdown::default());
+        let input =
+            get("one", "") + "POST /shutdown HTTP/1.1\r\nHost: localhost\r\n\r\n" + &get("two", "");
+        let (output, served) = exchange_until(KeepAlive::default(), &input, Arc::clone(&shutdown));
+
+        assert_eq!(2, served);
+        assert!(output
+            .ends_with("HTTP/1.1 202 Accepted\r\nConnection: close\r\nContent-Length: 0\r\n\r\n"));
+        assert_eq!(1, shutdown.drained());
+
+        // a connection waiting for its next request doesn't wait out the idle timeout
+        let shutdown = Arc::new(Shutdown::default());
+        let stopper = Arc::clone(&shutdown);
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(200));
+            stopper.request();
+        });
+
+        let start = Instant::now();
+        let (output, served) = exchange_until(KeepAlive::default(), &get("one", ""), shutdown);
+        assert_eq!(1, served);
+        assert!(output.ends_with("one"));
+        assert!(start.elapsed() < Duration::from_secs(2));
+    }
+}
@@ -1,3 +1,17 @@
+pub mod connection;
+pub mod request;
+pub mod response;
+pub mod router;
+pub mod shutdown;
+pub mod static_files;
+
+pub use connection::{serve_connection, KeepAlive};
+pub use request::{Headers, Limits, ParseError, Request, Version};
+pub use response::{Body, Response};
+pub use router::{Params, Router};
+pub use shutdown::Shutdown;
+pub use static_files::StaticFiles;
+
 use std::{
     error::Error,
     fmt,
@@ -94,7 +108,7 @@ impl ThreadPool {
         }
 
         ThreadPool {
-            workers: workers,
+            workers,
             sender: Some(sender),
         }
     }
@@ -1,73 +1,164 @@
 use std::{
-    fs,
-    io::{prelude::*, BufReader},
+    env,
     net::{TcpListener, TcpStream},
+    process,
+    sync::Arc,
     thread,
     time::Duration,
 };
 
-use hello::ThreadPool;
+use hello::{
+    serve_connection, KeepAlive, Limits, Request, Response, Router, Shutdown, StaticFiles,
+    ThreadPool,
+};
+
+/// How long requests still being answered get to finish once the server is shutting down.
+const DRAIN_DEADLINE: Duration = Duration::from_secs(30);
 
 fn main() {
     let listener = TcpListener::bind("127.0.0.0:7878").unwrap(); // returns a `TcpListener` instance
     let pool = ThreadPool::new(4);
 
-    for stream in listener.incoming().take(2) {
+    let shutdown = Arc::new(Shutdown::for_listener(&listener).unwrap());
+
+    // SIGINT and SIGTERM start a shutdown; a second one doesn't wait for it
+    let signaled = Arc::clone(&shutdown);
+    ctrlc::set_handler(move || {
+        if signaled.is_requested() {
+            process::exit(130);
+        }
+        println!("Shutdown requested.");
+        signaled.request();
+    })
+    .unwrap();
+
+    // the document root can be given as the first argument
+    let root = env::args().nth(1).unwrap_or_else(|| String::from("public"));
+    let files = StaticFiles::new(&root).unwrap_or_else(|error| {
+        eprintln!("Problem opening the document root {root}: {error}");
+        process::exit(1);
+    });
+
+    // adding an endpoint is adding a route; every worker shares the one router
+    let sleepy = files.clone();
+    let not_found = files.clone();
+    let mut router = Router::new();
+
+    // without a token to check, there is no way to shut down over http
+    if let Some(token) = env::var("HELLO_ADMIN_TOKEN")
+        .ok()
+        .filter(|token| !token.is_empty())
+    {
+        let shutdown = Arc::clone(&shutdown);
+
+        router = router.post("/admin/shutdown", move |request, _| {
+            if !authorized(request, &token) {
+                return Response::text(401, "Unauthorized")
+                    .with_header("WWW-Authenticate", "Bearer");
+            }
+
+            shutdown.request();
+            Response::text(202, "Shutting down")
+        });
+    }
+
+    let router = Arc::new(
+        router
+            .get("/sleep", move |_, _| {
+                thread::sleep(Duration::from_secs(10));
+                sleepy.page(200, "index.html")
+            })
+            .get("/hello/:name", |_, params| {
+                Response::text(200, format!("Hello, {}!", params.get("name").unwrap()))
+            })
+            .get("/*path", move |request, params| {
+                let response = files.serve(request, params.get("path").unwrap());
+
+                match response.status {
+                    404 => files.page(404, "404.html"),
+                    _ => response,
+                }
+            })
+            .not_found(move |_| not_found.page(404, "404.html")),
+    );
+
+    for stream in listener.incoming() {
+        // the connection that woke the listener up for the shutdown is not served
+        if shutdown.is_requested() {
+            break;
+        }
+
         // `incoming` returns an iterator of 'TcpStream` instances that represent external client connection attempts
-        let stream = stream.unwrap(); // this is how we handle a failed connection attempt
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(error) => {
+                eprintln!("Failed to accept a connection: {error}");
+                continue;
+            }
+        };
+        let router = Arc::clone(&router);
+        let shutdown = Arc::clone(&shutdown);
+        let tracked = shutdown.track();
 
-        pool.execute(|| {
-            handle_connection(stream);
+        pool.execute(move || {
+            handle_connection(stream, &router, &shutdown);
+            drop(tracked);
         });
     }
 
+    // no more connections are accepted from here on
+    drop(listener);
+
+    println!(
+        "Shutting down; waiting up to {}s for requests in flight.",
+        DRAIN_DEADLINE.as_secs()
+    );
+    let remaining = shutdown.wait(DRAIN_DEADLINE);
+    println!("Drained {} requests.", shutdown.drained());
+
+    // the workers still serving them would never be joined
+    if remaining > 0 {
+        eprintln!("Gave up on {remaining} connections still being served.");
+        process::exit(1);
+    }
+
     println!("Shutting down.");
 }
 
-fn handle_connection(mut stream: TcpStream) {
-    let buf_reader = BufReader::new(&mut stream);
-
-    //    let http_request: Vec<_> = buf_reader
-    //            .lines() /* this is a function from the `BufRead` trait. it return an iterator strings, separated by the line breaks */
-    //            .map(|result| result.unwrap()) /* unwrap each result */
-    //            .take_while(|line| !line.is_empty()) /* browers end requests with two `new-lines`. */
-    //            .collect(); /* collect all elements into a vector */
-    // unwrapping a result after unwrapping an option - if the option type is None,then the first unwrap panics
-    let request_line = buf_reader.lines().next().unwrap().unwrap();
-
-    //    if request_line == "GET / HTTP/1.1" {
-    //        let status_line = "HTTP/1.1 200 OK";
-    //        let contents = fs::read_to_string("hello.html").unwrap();
-    //        let length = contents.len(); // this length is used for the `contents-length` header
-    //
-    //        let response = format!("{status_line}\r\nContents-Length: {length}\r\n\r\n{contents}"); // build the string that will be used for the http response
-    //
-    //        stream.write_all(response.as_bytes()).unwrap(); // convert the string to bytes, pass the bytes as an argument to `write_all` and unwrap the result
-    //    } else {
-    //        let status_line = "HHTP/1.1 404 NOT FOUND";
-    //        let contents = fs::read_to_string("404.html").unwrap();
-    //
-    //        let response = format!("{status_line}\r\nContent-Lenght: {length}\r\n\r\n{contents}");
-    //
-    //        stream.write_all(response.as_bytes()).unwrap();
-    //    }
-
-    // match does not do automatic dereferencing, because it is not always desired
-    let (status_line, file_name) = match &request_line[..] {
-        "GET / HTTP/1.1" => ("HTTP/1.1 200 OK", "hello.html"),
-        "GET /sleep HTTP/1.1" => {
-            thread::sleep(Duration::from_secs(10));
-            ("HTTP/1.1 200 OK", "hello.html")
-        }
-        _ => ("HTTP/1.1 404 NOT FOUND", "404.html"),
-    };
+fn handle_connection(stream: TcpStream, router: &Router, shutdown: &Shutdown) {
+    // requests are answered until the client is done with the connection, goes quiet, or has sent
+    // enough of them that it's somebody else's turn for this worker
+    let served = serve_connection(
+        stream,
+        router,
+        &Limits::default(),
+        &KeepAlive::default(),
+        shutdown,
+    );
 
-    let contents = fs::read_to_string(file_name).unwrap();
-    let length = contents.len();
+    // the client may already be gone, and there is nobody else to tell
+    if let Err(error) = served {
+        eprintln!("Connection failed: {error}");
+    }
 
-    let response = format!("{status_line}\r\nContent-Length: {length}\r\n\r\n{contents}");
+    //design the public api, then implement the functionality
+}
 
-    stream.write_all(response.as_bytes()).unwrap();
+/// Whether `request` carries `token` as its bearer token. Every byte is compared, so how long it
+/// takes doesn't tell how much of a wrong token was right.
+fn authorized(request: &Request, token: &str) -> bool {
+    let Some(given) = request
+        .headers
+        .get("Authorization")
+        .and_then(|value| value.strip_prefix("Bearer "))
+    else {
+        return false;
+    };
 
-    //design the public api, then implement the functionality
+    given.len() == token.len()
+        && given
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |difference, (a, b)| difference | (a ^ b))
+            == 0
 }
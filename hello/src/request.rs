@@ -0,0 +1,515 @@
+use std::{
+    error::Error,
+    fmt,
+    io::{self, BufRead, Read},
+    str,
+};
+
+/// `Limits` struct and implementations
+///
+/// How big the parts of a request may be before it is refused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// Bytes in the request line, line break included.
+    pub request_line: usize,
+    /// Number of header fields.
+    pub headers: usize,
+    /// Bytes in all header lines together, line breaks included.
+    pub header_bytes: usize,
+    /// Bytes in the body.
+    pub body: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Limits {
+        Limits {
+            request_line: 8 * 1024,
+            headers: 100,
+            header_bytes: 16 * 1024,
+            body: 1024 * 1024,
+        }
+    }
+}
+
+/// `Version` enum and implementations
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Version {
+    Http10,
+    Http11,
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Version::Http10 => write!(f, "HTTP/1.0"),
+            Version::Http11 => write!(f, "HTTP/1.1"),
+        }
+    }
+}
+
+/// `Headers` struct and implementations
+///
+/// Header fields in the order they were sent. Names are compared without regard to case.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Headers {
+    fields: Vec<(String, String)>,
+}
+
+impl Headers {
+    pub fn new() -> Headers {
+        Headers::default()
+    }
+
+    /// The value of the first field called `name`.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(field, _)| field.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The values of every field called `name`.
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.fields
+            .iter()
+            .filter(move |(field, _)| field.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    /// Add a field, keeping any already called `name`.
+    pub fn append(&mut self, name: &str, value: &str) {
+        self.fields.push((name.to_string(), value.to_string()));
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.fields
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.fields.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+}
+
+/// `ParseError` enum and implementations
+#[derive(Debug)]
+pub enum ParseError {
+    /// The request doesn't follow the syntax of HTTP/1.1.
+    Malformed(&'static str),
+    RequestLineTooLong,
+    HeadersTooLarge,
+    BodyTooLarge,
+    UnsupportedVersion(String),
+    /// A `Transfer-Encoding` was given; only bodies with a `Content-Length` are read.
+    UnsupportedTransferEncoding,
+    /// The connection failed, or closed in the middle of a request.
+    Io(io::Error),
+}
+
+impl ParseError {
+    /// The status to answer with, or `None` when there is nobody left to answer.
+    pub fn status(&self) -> Option<u16> {
+        match self {
+            ParseError::Malformed(_) => Some(400),
+            ParseError::RequestLineTooLong => Some(414),
+            ParseError::HeadersTooLarge => Some(431),
+            ParseError::BodyTooLarge => Some(413),
+            ParseError::UnsupportedVersion(_) => Some(505),
+            ParseError::UnsupportedTransferEncoding => Some(501),
+            ParseError::Io(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Malformed(reason) => write!(f, "malformed request: {reason}"),
+            ParseError::RequestLineTooLong => write!(f, "request line too long"),
+            ParseError::HeadersTooLarge => write!(f, "header fields too large"),
+            ParseError::BodyTooLarge => write!(f, "body too large"),
+            ParseError::UnsupportedVersion(version) => {
+                write!(f, "unsupported version {version}")
+            }
+            ParseError::UnsupportedTransferEncoding => {
+                write!(f, "transfer encodings are not supported")
+            }
+            ParseError::Io(error) => write!(f, "{error}"),
+        }
+    }
+}
+
+impl Error for ParseError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ParseError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ParseError {
+    fn from(error: io::Error) -> ParseError {
+        ParseError::Io(error)
+    }
+}
+
+/// `Request` struct and implementations
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Request {
+    pub method: String,
+    /// The target exactly as sent, such as `/search?q=rust`.
+    pub target: String,
+    /// The target up to the query string, still percent-encoded.
+    pub path: String,
+    /// What follows the `?` of the target, if there is one.
+    pub query: Option<String>,
+    pub version: Version,
+    pub headers: Headers,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    /// Read one request from `reader`, refusing it as soon as it goes over `limits`.
+    pub fn read(reader: &mut impl BufRead, limits: &Limits) -> Result<Request, ParseError> {
+        let line = read_line(reader, limits.request_line, ParseError::RequestLineTooLong)?;
+        let (method, target, version) = parse_request_line(&line)?;
+
+        let (path, query) = match target.split_once('?') {
+            Some((path, query)) => (path.to_string(), Some(query.to_string())),
+            None => (target.to_string(), None),
+        };
+
+        let mut request = Request {
+            method: method.to_string(),
+            target: target.to_string(),
+            path,
+            query,
+            version,
+            headers: Headers::new(),
+            body: Vec::new(),
+        };
+
+        let mut header_bytes = 0;
+
+        loop {
+            let remaining = limits.header_bytes.saturating_sub(header_bytes);
+            let line = read_line(reader, remaining, ParseError::HeadersTooLarge)?;
+            header_bytes += line.len() + 2;
+
+            if line.is_empty() {
+                break;
+            }
+
+            if request.headers.len() == limits.headers {
+                return Err(ParseError::HeadersTooLarge);
+            }
+
+            let (name, value) = parse_header(&line)?;
+            request.headers.append(name, value);
+        }
+
+        if request.version == Version::Http11 && !request.headers.contains("Host") {
+            return Err(ParseError::Malformed("missing Host header"));
+        }
+
+        if request.headers.contains("Transfer-Encoding") {
+            return Err(ParseError::UnsupportedTransferEncoding);
+        }
+
+        let length = content_length(&request.headers)?;
+        if length > limits.body {
+            return Err(ParseError::BodyTooLarge);
+        }
+
+        request.body = vec![0; length];
+        reader.read_exact(&mut request.body)?;
+
+        Ok(request)
+    }
+
+    /// The values in the query string, percent-decoded, in the order they were sent.
+    pub fn query_pairs(&self) -> Vec<(String, String)> {
+        let Some(query) = &self.query else {
+            return Vec::new();
+        };
+
+        query
+            .split('&')
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| {
+                let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+                let decode = |s: &str| {
+                    let s = s.replace('+', " ");
+                    percent_decode(&s).unwrap_or(s)
+                };
+                (decode(name), decode(value))
+            })
+            .collect()
+    }
+
+    /// The first value called `name` in the query string.
+    pub fn query_param(&self, name: &str) -> Option<String> {
+        self.query_pairs()
+            .into_iter()
+            .find(|(param, _)| param == name)
+            .map(|(_, value)| value)
+    }
+}
+
+/// Decode the `%XX` escapes in `s`. `None` if an escape is cut short or isn't hex, or the result
+/// isn't UTF-8.
+pub fn percent_decode(s: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut rest = s.as_bytes();
+
+    while let Some((&byte, after)) = rest.split_first() {
+        if byte == b'%' {
+            // `from_str_radix` would take a sign too, as in `%+1`
+            let hex = after
+                .get(..2)
+                .filter(|hex| hex.iter().all(u8::is_ascii_hexdigit))?;
+            bytes.push(u8::from_str_radix(str::from_utf8(hex).ok()?, 16).ok()?);
+            rest = &after[2..];
+        } else {
+            bytes.push(byte);
+            rest = after;
+        }
+    }
+
+    String::from_utf8(bytes).ok()
+}
+
+/// Read a line of at most `limit` bytes and strip its line break, CRLF or a bare LF.
+fn read_line(
+    reader: &mut impl BufRead,
+    limit: usize,
+    too_long: ParseError,
+) -> Result<String, ParseError> {
+    let mut line = Vec::new();
+    reader
+        .by_ref()
+        .take(limit as u64)
+        .read_until(b'\n', &mut line)?;
+
+    if line.last() != Some(&b'\n') {
+        return if line.len() == limit {
+            Err(too_long)
+        } else {
+            Err(io::Error::from(io::ErrorKind::UnexpectedEof).into())
+        };
+    }
+
+    line.pop();
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+
+    String::from_utf8(line).map_err(|_| ParseError::Malformed("not valid UTF-8"))
+}
+
+fn parse_request_line(line: &str) -> Result<(&str, &str, Version), ParseError> {
+    let mut parts = line.split(' ');
+
+    let (Some(method), Some(target), Some(version), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return Err(ParseError::Malformed(
+            "request line must be METHOD TARGET VERSION",
+        ));
+    };
+
+    if !is_token(method) {
+        return Err(ParseError::Malformed("invalid method"));
+    }
+
+    // origin-form, or `*` for a request about the server as a whole
+    let origin_form = target.starts_with('/') && !target.chars().any(|c| c.is_ascii_control());
+    if !origin_form && target != "*" {
+        return Err(ParseError::Malformed("invalid request target"));
+    }
+
+    let version = match version {
+        "HTTP/1.1" => Version::Http11,
+        "HTTP/1.0" => Version::Http10,
+        _ if version.starts_with("HTTP/") => {
+            return Err(ParseError::UnsupportedVersion(version.to_string()))
+        }
+        _ => return Err(ParseError::Malformed("invalid version")),
+    };
+
+    Ok((method, target, version))
+}
+
+fn parse_header(line: &str) -> Result<(&str, &str), ParseError> {
+    // lines folded onto the previous one are obsolete, and unsafe to guess at
+    if line.starts_with([' ', '\t']) {
+        return Err(ParseError::Malformed("folded header line"));
+    }
+
+    let Some((name, value)) = line.split_once(':') else {
+        return Err(ParseError::Malformed("header line without a colon"));
+    };
+
+    // whitespace before the colon has been used to smuggle requests past proxies
+    if !is_token(name) {
+        return Err(ParseError::Malformed("invalid header name"));
+    }
+
+    let value = value.trim_matches([' ', '\t']);
+    if value.chars().any(|c| c.is_ascii_control() && c != '\t') {
+        return Err(ParseError::Malformed("invalid header value"));
+    }
+
+    Ok((name, value))
+}
+
+/// The length of the body: 0 without a `Content-Length`, and an error unless every
+/// `Content-Length` agrees.
+fn content_length(headers: &Headers) -> Result<usize, ParseError> {
+    let mut length = None;
+
+    for value in headers.get_all("Content-Length") {
+        let parsed = value
+            .parse::<usize>()
+            .ok()
+            .filter(|_| value.bytes().all(|b| b.is_ascii_digit()))
+            .ok_or(ParseError::Malformed("invalid Content-Length"))?;
+
+        if length.is_some_and(|length| length != parsed) {
+            return Err(ParseError::Malformed("conflicting Content-Length"));
+        }
+        length = Some(parsed);
+    }
+
+    Ok(length.unwrap_or(0))
+}
+
+/// Whether `s` is a token, the syntax of methods and header names.
+fn is_token(s: &str) -> bool {
+    !s.is_empty()
+        && s.bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(input: &str) -> Result<Request, ParseError> {
+        Request::read(&mut input.as_bytes(), &Limits::default())
+    }
+
+    #[test]
+    fn request_line_headers_and_body() {
+        let request = parse(
+            "POST /search?q=rust+book&page=2 HTTP/1.1\r\n\
+             Host: localhost\r\n\
+             content-length: 5\r\n\
+             X-Tag:  a \r\n\
+             X-Tag: b\r\n\
+             \r\n\
+             hello, and the next request",
+        )
+        .unwrap();
+
+        assert_eq!("POST", request.method);
+        assert_eq!("/search?q=rust+book&page=2", request.target);
+        assert_eq!("/search", request.path);
+        assert_eq!(Version::Http11, request.version);
+        assert_eq!(Some("5"), request.headers.get("Content-Length"));
+        assert_eq!(
+            vec!["a", "b"],
+            request.headers.get_all("x-tag").collect::<Vec<_>>()
+        );
+        assert_eq!(b"hello", request.body.as_slice());
+        assert_eq!(Some(String::from("rust book")), request.query_param("q"));
+        assert_eq!(Some(String::from("2")), request.query_param("page"));
+    }
+
+    #[test]
+    fn bare_line_feeds_and_http_1_0() {
+        let request = parse("GET / HTTP/1.0\n\n").unwrap();
+
+        assert_eq!(Version::Http10, request.version);
+        assert!(request.headers.is_empty());
+        assert!(request.body.is_empty());
+    }
+
+    #[test]
+    fn malformed_requests() {
+        let malformed = [
+            "GET /\r\n\r\n",
+            "GET  / HTTP/1.1\r\nHost: a\r\n\r\n",
+            "G(T / HTTP/1.1\r\nHost: a\r\n\r\n",
+            "GET index.html HTTP/1.1\r\nHost: a\r\n\r\n",
+            "GET / HTTP/1.1\r\n\r\n",
+            "GET / HTTP/1.1\r\nHost: a\r\nX-Name : value\r\n\r\n",
+            "GET / HTTP/1.1\r\nHost: a\r\n folded\r\n\r\n",
+            "GET / HTTP/1.1\r\nHost: a\r\nno colon\r\n\r\n",
+            "POST / HTTP/1.1\r\nHost: a\r\nContent-Length: -1\r\n\r\n",
+            "POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 1\r\nContent-Length: 2\r\n\r\nab",
+        ];
+
+        for input in malformed {
+            let error = parse(input).unwrap_err();
+            assert_eq!(Some(400), error.status(), "{input:?}: {error}");
+        }
+
+        let error = parse("GET / HTTP/2.0\r\n\r\n").unwrap_err();
+        assert!(matches!(error, ParseError::UnsupportedVersion(_)));
+    }
+
+    #[test]
+    fn limits_are_enforced() {
+        let limits = Limits {
+            request_line: 32,
+            headers: 2,
+            header_bytes: 64,
+            body: 4,
+        };
+        let parse = |input: &str| Request::read(&mut input.as_bytes(), &limits).unwrap_err();
+
+        let long_target = format!("GET /{} HTTP/1.1\r\n\r\n", "a".repeat(32));
+        assert!(matches!(
+            parse(&long_target),
+            ParseError::RequestLineTooLong
+        ));
+        assert!(matches!(
+            parse("GET / HTTP/1.1\r\nHost: a\r\nA: 1\r\nB: 2\r\n\r\n"),
+            ParseError::HeadersTooLarge
+        ));
+        let long_header = format!("GET / HTTP/1.1\r\nHost: {}\r\n\r\n", "a".repeat(64));
+        assert!(matches!(parse(&long_header), ParseError::HeadersTooLarge));
+        assert!(matches!(
+            parse("POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 5\r\n\r\nhello"),
+            ParseError::BodyTooLarge
+        ));
+        // the connection closing early is nothing to answer
+        assert!(matches!(
+            parse("POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 3\r\n\r\nhi"),
+            ParseError::Io(_)
+        ));
+    }
+
+    #[test]
+    fn decode_percent_escapes() {
+        assert_eq!(
+            Some(String::from("a b/ü")),
+            percent_decode("a%20b%2F%C3%BC")
+        );
+        assert_eq!(None, percent_decode("50%"));
+        assert_eq!(None, percent_decode("%ff"));
+        assert_eq!(None, percent_decode("%+1"));
+        assert_eq!(None, percent_decode("%-1"));
+    }
+}
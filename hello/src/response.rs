@@ -0,0 +1,174 @@
+use std::{
+    fs::File,
+    io::{self, Read, Write},
+};
+
+use crate::request::Headers;
+
+/// `Body` enum and implementations
+#[derive(Debug)]
+pub enum Body {
+    Bytes(Vec<u8>),
+    /// `length` bytes of `file`, read a buffer at a time as the response is written.
+    File {
+        file: File,
+        length: u64,
+    },
+}
+
+impl Default for Body {
+    fn default() -> Body {
+        Body::Bytes(Vec::new())
+    }
+}
+
+impl Body {
+    pub fn len(&self) -> u64 {
+        match self {
+            Body::Bytes(bytes) => bytes.len() as u64,
+            Body::File { length, .. } => *length,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The body, unless it still has to be read from a file.
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Body::Bytes(bytes) => Some(bytes),
+            Body::File { .. } => None,
+        }
+    }
+}
+
+impl From<Vec<u8>> for Body {
+    fn from(bytes: Vec<u8>) -> Body {
+        Body::Bytes(bytes)
+    }
+}
+
+impl From<String> for Body {
+    fn from(text: String) -> Body {
+        Body::Bytes(text.into_bytes())
+    }
+}
+
+impl From<&str> for Body {
+    fn from(text: &str) -> Body {
+        Body::Bytes(text.as_bytes().to_vec())
+    }
+}
+
+/// `Response` struct and implementations
+#[derive(Debug)]
+pub struct Response {
+    pub status: u16,
+    pub headers: Headers,
+    pub body: Body,
+}
+
+impl Response {
+    /// An empty response with `status`.
+    pub fn new(status: u16) -> Response {
+        Response {
+            status,
+            headers: Headers::new(),
+            body: Body::default(),
+        }
+    }
+
+    pub fn text(status: u16, body: impl Into<String>) -> Response {
+        Response::new(status)
+            .with_header("Content-Type", "text/plain; charset=utf-8")
+            .with_body(body.into())
+    }
+
+    pub fn html(status: u16, body: impl Into<String>) -> Response {
+        Response::new(status)
+            .with_header("Content-Type", "text/html; charset=utf-8")
+            .with_body(body.into())
+    }
+
+    pub fn with_header(mut self, name: &str, value: &str) -> Response {
+        self.headers.append(name, value);
+        self
+    }
+
+    pub fn with_body(mut self, body: impl Into<Body>) -> Response {
+        self.body = body.into();
+        self
+    }
+
+    /// Write the status line, the headers and the body. A `Content-Length` is added for the body
+    /// unless one was already set.
+    pub fn write_to(&self, writer: &mut impl Write) -> io::Result<()> {
+        let mut head = format!("HTTP/1.1 {} {}\r\n", self.status, reason(self.status));
+
+        for (name, value) in self.headers.iter() {
+            head.push_str(&format!("{name}: {value}\r\n"));
+        }
+        if !self.headers.contains("Content-Length") {
+            head.push_str(&format!("Content-Length: {}\r\n", self.body.len()));
+        }
+        head.push_str("\r\n");
+
+        writer.write_all(head.as_bytes())?;
+        match &self.body {
+            Body::Bytes(bytes) => writer.write_all(bytes)?,
+            Body::File { file, length } => {
+                let copied = io::copy(&mut file.take(*length), writer)?;
+                // the file shrank after its length was sent, so the response can't be finished
+                if copied < *length {
+                    return Err(io::ErrorKind::UnexpectedEof.into());
+                }
+            }
+        }
+        writer.flush()
+    }
+}
+
+/// The reason phrase that goes with `status` in a status line.
+pub fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        202 => "Accepted",
+        204 => "No Content",
+        301 => "Moved Permanently",
+        304 => "Not Modified",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        413 => "Content Too Large",
+        414 => "URI Too Long",
+        431 => "Request Header Fields Too Large",
+        500 => "Internal Server Error",
+        501 => "Not Implemented",
+        503 => "Service Unavailable",
+        505 => "HTTP Version Not Supported",
+        _ => "",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_status_headers_and_body() {
+        let mut output = Vec::new();
+        Response::text(404, "nope").write_to(&mut output).unwrap();
+
+        assert_eq!(
+            "HTTP/1.1 404 Not Found\r\n\
+             Content-Type: text/plain; charset=utf-8\r\n\
+             Content-Length: 4\r\n\
+             \r\n\
+             nope",
+            String::from_utf8(output).unwrap()
+        );
+    }
+}
@@ -0,0 +1,336 @@
+use std::panic::{self, AssertUnwindSafe};
+
+use crate::{request::percent_decode, response::Body, Request, Response};
+
+/// `Params` struct and implementations
+///
+/// The parts of a path picked out by the `:name` and `*name` segments of a route, percent-decoded.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Params {
+    params: Vec<(String, String)>,
+}
+
+impl Params {
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(param, _)| param == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.params
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Param(String),
+    /// Everything that's left of the path, `*` when it has no name.
+    Wildcard(String),
+}
+
+/// `Pattern` struct and implementations
+///
+/// A path pattern such as `/users/:id` or `/static/*path`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Pattern {
+    segments: Vec<Segment>,
+}
+
+impl Pattern {
+    /// # Panics
+    ///
+    /// Panics if `pattern` doesn't start with `/`, or if a wildcard isn't its last segment.
+    fn parse(pattern: &str) -> Pattern {
+        let rest = pattern
+            .strip_prefix('/')
+            .unwrap_or_else(|| panic!("route pattern {pattern:?} must start with '/'"));
+
+        let segments: Vec<_> = rest
+            .split('/')
+            .map(|segment| {
+                if let Some(name) = segment.strip_prefix(':') {
+                    Segment::Param(name.to_string())
+                } else if let Some(name) = segment.strip_prefix('*') {
+                    let name = if name.is_empty() { "*" } else { name };
+                    Segment::Wildcard(name.to_string())
+                } else {
+                    Segment::Literal(segment.to_string())
+                }
+            })
+            .collect();
+
+        let wildcard = segments
+            .iter()
+            .position(|segment| matches!(segment, Segment::Wildcard(_)));
+        if wildcard.is_some_and(|position| position + 1 != segments.len()) {
+            panic!("the wildcard of route pattern {pattern:?} must be its last segment");
+        }
+
+        Pattern { segments }
+    }
+
+    /// The parameters of `path`, if it matches.
+    fn matches(&self, path: &str) -> Option<Params> {
+        let mut parts = path.strip_prefix('/')?.split('/');
+        let mut params = Params::default();
+        let decode = |s: &str| percent_decode(s).unwrap_or_else(|| s.to_string());
+
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(literal) => {
+                    if parts.next()? != literal {
+                        return None;
+                    }
+                }
+                Segment::Param(name) => {
+                    let part = parts.next().filter(|part| !part.is_empty())?;
+                    params.params.push((name.clone(), decode(part)));
+                }
+                Segment::Wildcard(name) => {
+                    let rest: Vec<_> = parts.by_ref().collect();
+                    if rest.is_empty() {
+                        return None;
+                    }
+                    params.params.push((name.clone(), decode(&rest.join("/"))));
+                }
+            }
+        }
+
+        match parts.next() {
+            Some(_) => None,
+            None => Some(params),
+        }
+    }
+}
+
+type Handler = Box<dyn Fn(&Request, &Params) -> Response + Send + Sync>;
+type NotFound = Box<dyn Fn(&Request) -> Response + Send + Sync>;
+
+struct Route {
+    method: String,
+    pattern: Pattern,
+    handler: Handler,
+}
+
+/// `Router` struct and implementations
+///
+/// Sends each request to the handler of the first route, in the order they were added, whose
+/// method and pattern match. `GET` routes answer `HEAD` requests too, without the body.
+///
+/// A pattern is made of `/`-separated segments: literal text, `:name` to match any one non-empty
+/// segment, and, as the last segment, `*name` or `*` to match everything that's left.
+pub struct Router {
+    routes: Vec<Route>,
+    not_found: Option<NotFound>,
+}
+
+impl Default for Router {
+    fn default() -> Router {
+        Router::new()
+    }
+}
+
+impl Router {
+    pub fn new() -> Router {
+        Router {
+            routes: Vec::new(),
+            not_found: None,
+        }
+    }
+
+    /// Add a route for requests with `method` to a path matching `pattern`.
+    ///
+    /// # Panics
+    ///
+    /// The `route` function will panic if `pattern` doesn't start with `/`, or has a wildcard
+    /// anywhere but at its end.
+    pub fn route<F>(mut self, method: &str, pattern: &str, handler: F) -> Router
+    where
+        F: Fn(&Request, &Params) -> Response + Send + Sync + 'static,
+    {
+        self.routes.push(Route {
+            method: method.to_string(),
+            pattern: Pattern::parse(pattern),
+            handler: Box::new(handler),
+        });
+        self
+    }
+
+    pub fn get<F>(self, pattern: &str, handler: F) -> Router
+    where
+        F: Fn(&Request, &Params) -> Response + Send + Sync + 'static,
+    {
+        self.route("GET", pattern, handler)
+    }
+
+    pub fn post<F>(self, pattern: &str, handler: F) -> Router
+    where
+        F: Fn(&Request, &Params) -> Response + Send + Sync + 'static,
+    {
+        self.route("POST", pattern, handler)
+    }
+
+    /// Answer requests that no route matches with `handler` instead of a plain 404.
+    pub fn not_found<F>(mut self, handler: F) -> Router
+    where
+        F: Fn(&Request) -> Response + Send + Sync + 'static,
+    {
+        self.not_found = Some(Box::new(handler));
+        self
+    }
+
+    /// The response to `request`: 404 when no route matches its path, 405 when routes match it
+    /// but not its method, and 500 if the handler panics.
+    pub fn handle(&self, request: &Request) -> Response {
+        let head = request.method == "HEAD";
+        let mut allowed: Vec<&str> = Vec::new();
+
+        for route in &self.routes {
+            let Some(params) = route.pattern.matches(&request.path) else {
+                continue;
+            };
+
+            if route.method == request.method || (head && route.method == "GET") {
+                let mut response = call(|| (route.handler)(request, &params));
+
+                if head {
+                    let length = response.body.len().to_string();
+                    if !response.headers.contains("Content-Length") {
+                        response.headers.append("Content-Length", &length);
+                    }
+                    response.body = Body::default();
+                }
+
+                return response;
+            }
+
+            let methods: &[&str] = match route.method.as_str() {
+                "GET" => &["GET", "HEAD"],
+                method => &[method],
+            };
+            for method in methods {
+                if !allowed.contains(method) {
+                    allowed.push(method);
+                }
+            }
+        }
+
+        if !allowed.is_empty() {
+            return Response::text(405, "Method Not Allowed")
+                .with_header("Allow", &allowed.join(", "));
+        }
+
+        match &self.not_found {
+            Some(not_found) => call(|| not_found(request)),
+            None => Response::text(404, "Not Found"),
+        }
+    }
+}
+
+/// Call a handler, turning a panic into a 500 so it doesn't take the worker down with it.
+fn call(handler: impl FnOnce() -> Response) -> Response {
+    panic::catch_unwind(AssertUnwindSafe(handler))
+        .unwrap_or_else(|_| Response::text(500, "Internal Server Error"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Limits;
+
+    fn request(method: &str, target: &str) -> Request {
+        let input = format!("{method} {target} HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        Request::read(&mut input.as_bytes(), &Limits::default()).unwrap()
+    }
+
+    fn body(response: &Response) -> &str {
+        std::str::from_utf8(response.body.as_bytes().unwrap()).unwrap()
+    }
+
+    fn router() -> Router {
+        Router::new()
+            .get("/", |_, _| Response::text(200, "home"))
+            .get("/users/:id", |_, params| {
+                Response::text(200, format!("user {}", params.get("id").unwrap()))
+            })
+            .get("/users/:id/posts/:post", |_, params| {
+                let id = params.get("id").unwrap();
+                Response::text(200, format!("{id}/{}", params.get("post").unwrap()))
+            })
+            .post("/users", |request, _| Response::text(201, body_of(request)))
+            .get("/static/*path", |_, params| {
+                Response::text(200, params.get("path").unwrap().to_string())
+            })
+            .get("/panic", |_, _| panic!("handler bug"))
+    }
+
+    fn body_of(request: &Request) -> String {
+        String::from_utf8_lossy(&request.body).into_owned()
+    }
+
+    #[test]
+    fn parameters_and_wildcards() {
+        let router = router();
+
+        assert_eq!("home", body(&router.handle(&request("GET", "/"))));
+        assert_eq!(
+            "user 42",
+            body(&router.handle(&request("GET", "/users/42")))
+        );
+        assert_eq!(
+            "user a b",
+            body(&router.handle(&request("GET", "/users/a%20b?x=1")))
+        );
+        assert_eq!(
+            "7/9",
+            body(&router.handle(&request("GET", "/users/7/posts/9")))
+        );
+        assert_eq!(
+            "css/site.css",
+            body(&router.handle(&request("GET", "/static/css/site.css")))
+        );
+    }
+
+    #[test]
+    fn not_found_and_method_not_allowed() {
+        let router = router();
+
+        for target in ["/users/", "/users/7/posts", "/static", "/nope"] {
+            let response = router.handle(&request("GET", target));
+            assert_eq!(404, response.status, "{target}");
+        }
+
+        let response = router.handle(&request("DELETE", "/users/7"));
+        assert_eq!(405, response.status);
+        assert_eq!(Some("GET, HEAD"), response.headers.get("Allow"));
+
+        let response = router.handle(&request("PUT", "/users"));
+        assert_eq!(Some("POST"), response.headers.get("Allow"));
+
+        let router = Router::new().not_found(|request| Response::text(404, request.path.clone()));
+        assert_eq!("/nope", body(&router.handle(&request("GET", "/nope"))));
+    }
+
+    #[test]
+    fn head_and_panicking_handlers() {
+        let router = router();
+
+        let response = router.handle(&request("HEAD", "/users/42"));
+        assert_eq!(200, response.status);
+        assert_eq!(Some("7"), response.headers.get("Content-Length"));
+        assert!(response.body.is_empty());
+
+        assert_eq!(500, router.handle(&request("GET", "/panic")).status);
+    }
+
+    #[test]
+    #[should_panic(expected = "must be its last segment")]
+    fn wildcards_only_at_the_end() {
+        Router::new().get("/*rest/more", |_, _| Response::new(200));
+    }
+}
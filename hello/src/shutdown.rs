@@ -0,0 +1,159 @@
+use std::{
+    io,
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Condvar, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+/// `Shutdown` struct and implementations
+///
+/// Tells everything taking part in serving that the server is stopping, and keeps count of the
+/// connections still being served so they can be waited for.
+#[derive(Debug, Default)]
+pub struct Shutdown {
+    requested: AtomicBool,
+    /// The listener to wake up, which would otherwise go on waiting for a connection.
+    listener: Option<SocketAddr>,
+    connections: Mutex<usize>,
+    finished: Condvar,
+    drained: AtomicUsize,
+}
+
+impl Shutdown {
+    /// A shutdown that wakes up a thread waiting for `listener` to accept a connection.
+    pub fn for_listener(listener: &TcpListener) -> io::Result<Shutdown> {
+        Ok(Shutdown {
+            listener: Some(listener.local_addr()?),
+            ..Shutdown::default()
+        })
+    }
+
+    /// Start shutting down. Returns whether it had already been started.
+    pub fn request(&self) -> bool {
+        let already = self.requested.swap(true, Ordering::SeqCst);
+
+        if !already {
+            if let Some(address) = self.listener {
+                // the accepting thread sees the shutdown as soon as this connection wakes it
+                let _ = TcpStream::connect(address);
+            }
+        }
+
+        already
+    }
+
+    pub fn is_requested(&self) -> bool {
+        self.requested.load(Ordering::SeqCst)
+    }
+
+    /// Count a connection as being served until the returned guard is dropped.
+    pub fn track(self: &Arc<Self>) -> Tracked {
+        *self.connections.lock().unwrap() += 1;
+        Tracked {
+            shutdown: Arc::clone(self),
+        }
+    }
+
+    /// Note that a request was answered; the ones answered after the shutdown started are
+    /// counted as drained.
+    pub fn answered(&self) {
+        if self.is_requested() {
+            self.drained.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    /// The number of requests answered since the shutdown started.
+    pub fn drained(&self) -> usize {
+        self.drained.load(Ordering::SeqCst)
+    }
+
+    /// Wait up to `deadline` for every tracked connection to finish. Returns the number of
+    /// connections still being served when it gave up, 0 if they all finished.
+    pub fn wait(&self, deadline: Duration) -> usize {
+        let end = Instant::now() + deadline;
+        let mut connections = self.connections.lock().unwrap();
+
+        while *connections > 0 {
+            let left = end.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                break;
+            }
+
+            connections = self.finished.wait_timeout(connections, left).unwrap().0;
+        }
+
+        *connections
+    }
+}
+
+/// `Tracked` struct and implementations
+///
+/// The guard returned by `Shutdown::track`.
+#[derive(Debug)]
+pub struct Tracked {
+    shutdown: Arc<Shutdown>,
+}
+
+impl Drop for Tracked {
+    fn drop(&mut self) {
+        let mut connections = self.shutdown.connections.lock().unwrap();
+        *connections -= 1;
+
+        if *connections == 0 {
+            self.shutdown.finished.notify_all();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn wakes_the_listener() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let shutdown = Arc::new(Shutdown::for_listener(&listener).unwrap());
+
+        let requester = Arc::clone(&shutdown);
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            assert!(!requester.request());
+            assert!(requester.request());
+        });
+
+        let mut accepted = 0;
+        for _ in listener.incoming() {
+            accepted += 1;
+            if shutdown.is_requested() {
+                break;
+            }
+        }
+
+        assert_eq!(1, accepted);
+    }
+
+    #[test]
+    fn wait_for_connections_until_the_deadline() {
+        let shutdown = Arc::new(Shutdown::default());
+
+        // only what's answered after the shutdown starts is drained
+        shutdown.answered();
+        shutdown.request();
+
+        let quick = shutdown.track();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            quick.shutdown.answered();
+        });
+
+        assert_eq!(0, shutdown.wait(Duration::from_secs(5)));
+        assert_eq!(1, shutdown.drained());
+
+        let _stuck = shutdown.track();
+        assert_eq!(1, shutdown.wait(Duration::from_millis(50)));
+    }
+}
@@ -0,0 +1,263 @@
+use std::{
+    fs::File,
+    io,
+    path::{Component, Path, PathBuf},
+};
+
+use crate::{response::reason, Body, Request, Response};
+
+/// `StaticFiles` struct and implementations
+///
+/// Serves the files under a document root. A directory is served by its `index.html`, and
+/// nothing outside the root is ever served: not through `..`, and not through a symlink that
+/// points out of it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StaticFiles {
+    /// Canonical, so what a path resolves to can be checked against it.
+    root: PathBuf,
+}
+
+impl StaticFiles {
+    /// Serve the files under `root`, which has to be a directory.
+    pub fn new(root: impl AsRef<Path>) -> io::Result<StaticFiles> {
+        let root = root.as_ref().canonicalize()?;
+
+        if !root.is_dir() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} is not a directory", root.display()),
+            ));
+        }
+
+        Ok(StaticFiles { root })
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// The response to `request` for the file at `path`, percent-decoded and relative to the
+    /// root, like the `*path` of a route.
+    ///
+    /// A directory requested without a trailing `/` is redirected to one, so the relative links
+    /// of its `index.html` work.
+    pub fn serve(&self, request: &Request, path: &str) -> Response {
+        let file = match self.resolve(path) {
+            Ok(file) => file,
+            Err(status) => return Response::text(status, reason(status)),
+        };
+
+        if file.is_dir() {
+            if !path.is_empty() && !path.ends_with('/') {
+                // `//example.com/` would send the client to another host
+                let location = format!("/{}/", request.path.trim_start_matches('/'));
+                return Response::new(301).with_header("Location", &location);
+            }
+
+            return self.page(200, &format!("{path}/index.html"));
+        }
+
+        self.page(200, path)
+    }
+
+    /// The file at `path` with `status`, or a plain response with the status that says why it
+    /// can't be served. Directories aren't served.
+    pub fn page(&self, status: u16, path: &str) -> Response {
+        let opened = self.resolve(path).and_then(|file| {
+            let opened = File::open(&file).map_err(|error| status_for(&error))?;
+            let metadata = opened.metadata().map_err(|error| status_for(&error))?;
+
+            if !metadata.is_file() {
+                return Err(404);
+            }
+
+            Ok((file, opened, metadata.len()))
+        });
+
+        match opened {
+            Ok((file, opened, length)) => Response::new(status)
+                .with_header("Content-Type", mime_type(&file))
+                .with_body(Body::File {
+                    file: opened,
+                    length,
+                }),
+            Err(status) => Response::text(status, reason(status)),
+        }
+    }
+
+    /// Where `path` leads, once every symlink is followed, or the status to answer with if that's
+    /// nowhere or somewhere outside the root.
+    fn resolve(&self, path: &str) -> Result<PathBuf, u16> {
+        let mut file = self.root.clone();
+
+        for segment in path.split('/') {
+            // each segment has to be one plain name, which rules out `..`, but also `\` on
+            // Windows, drive prefixes and the NUL that would cut the path short
+            let mut components = Path::new(segment).components();
+
+            match (components.next(), components.next()) {
+                (None | Some(Component::CurDir), None) => {}
+                (Some(Component::Normal(name)), None) if !segment.contains('\0') => file.push(name),
+                _ => return Err(403),
+            }
+        }
+
+        let file = file.canonicalize().map_err(|error| status_for(&error))?;
+
+        if !file.starts_with(&self.root) {
+            return Err(403);
+        }
+
+        Ok(file)
+    }
+}
+
+/// The status that tells a client why a file couldn't be opened.
+fn status_for(error: &io::Error) -> u16 {
+    match error.kind() {
+        // a file can't be in a "directory" that is a file either
+        io::ErrorKind::NotFound | io::ErrorKind::NotADirectory => 404,
+        io::ErrorKind::PermissionDenied => 403,
+        _ => 500,
+    }
+}
+
+/// The `Content-Type` of a file, going by its extension.
+pub fn mime_type(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or("")
+        .to_ascii_lowercase();
+
+    match extension.as_str() {
+        "html" | "htm" => "text/html; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "js" | "mjs" => "text/javascript; charset=utf-8",
+        "json" => "application/json",
+        "txt" | "md" => "text/plain; charset=utf-8",
+        "csv" => "text/csv; charset=utf-8",
+        "xml" => "application/xml",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "ico" => "image/x-icon",
+        "wasm" => "application/wasm",
+        "pdf" => "application/pdf",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "ttf" => "font/ttf",
+        "mp3" => "audio/mpeg",
+        "wav" => "audio/wav",
+        "mp4" => "video/mp4",
+        "webm" => "video/webm",
+        "zip" => "application/zip",
+        "gz" => "application/gzip",
+        _ => "application/octet-stream",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Limits;
+    use std::fs;
+
+    fn get(target: &str) -> Request {
+        let input = format!("GET {target} HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        Request::read(&mut input.as_bytes(), &Limits::default()).unwrap()
+    }
+
+    /// The status and the body, with the file read into it.
+    fn serve(files: &StaticFiles, path: &str) -> (u16, Vec<u8>) {
+        // the request only matters to redirects
+        let response = files.serve(&get("/"), path);
+        let mut output = Vec::new();
+        response.write_to(&mut output).unwrap();
+
+        let start = output.windows(4).position(|w| w == b"\r\n\r\n").unwrap() + 4;
+        (response.status, output[start..].to_vec())
+    }
+
+    #[test]
+    fn files_index_and_redirects() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("docs")).unwrap();
+        fs::write(dir.path().join("index.html"), "home").unwrap();
+        fs::write(dir.path().join("docs/index.html"), "docs").unwrap();
+        fs::write(dir.path().join("logo.PNG"), [0x89, b'P', 0, 0xff]).unwrap();
+        fs::create_dir(dir.path().join("empty")).unwrap();
+
+        let files = StaticFiles::new(dir.path()).unwrap();
+
+        assert_eq!((200, b"home".to_vec()), serve(&files, ""));
+        assert_eq!((200, b"docs".to_vec()), serve(&files, "docs/"));
+        assert_eq!((200, vec![0x89, b'P', 0, 0xff]), serve(&files, "logo.PNG"));
+        assert_eq!(404, serve(&files, "missing.html").0);
+        assert_eq!(404, serve(&files, "index.html/more").0);
+        assert_eq!(404, serve(&files, "empty/").0);
+
+        let response = files.serve(&get("/docs"), "docs");
+        assert_eq!(301, response.status);
+        assert_eq!(Some("/docs/"), response.headers.get("Location"));
+
+        let response = files.serve(&get("//docs"), "/docs");
+        assert_eq!(Some("/docs/"), response.headers.get("Location"));
+
+        let response = files.serve(&get("/logo.PNG"), "logo.PNG");
+        assert_eq!(Some("image/png"), response.headers.get("Content-Type"));
+        assert_eq!(4, response.body.len());
+    }
+
+    #[test]
+    fn nothing_outside_the_root() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("public");
+        fs::create_dir(&root).unwrap();
+        fs::write(dir.path().join("secret.txt"), "secret").unwrap();
+        fs::write(root.join("page.html"), "page").unwrap();
+
+        let files = StaticFiles::new(&root).unwrap();
+
+        for path in [
+            "../secret.txt",
+            "./../secret.txt",
+            "a/../../secret.txt",
+            "..",
+            "a\0b",
+        ] {
+            assert_eq!(403, serve(&files, path).0, "{path:?}");
+        }
+        assert_eq!((200, b"page".to_vec()), serve(&files, "./page.html"));
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::symlink;
+
+            symlink(dir.path().join("secret.txt"), root.join("escape.txt")).unwrap();
+            symlink(dir.path(), root.join("escape")).unwrap();
+            symlink(root.join("page.html"), root.join("inside.html")).unwrap();
+
+            assert_eq!(403, serve(&files, "escape.txt").0);
+            assert_eq!(403, serve(&files, "escape/secret.txt").0);
+            assert_eq!((200, b"page".to_vec()), serve(&files, "inside.html"));
+        }
+    }
+
+    #[test]
+    fn page_with_any_status() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("404.html"), "gone").unwrap();
+
+        let files = StaticFiles::new(dir.path()).unwrap();
+
+        let response = files.page(404, "404.html");
+        assert_eq!(404, response.status);
+        assert_eq!(4, response.body.len());
+
+        assert_eq!(404, files.page(200, "missing.html").status);
+        assert!(StaticFiles::new(dir.path().join("404.html")).is_err());
+    }
+}
@@ -0,0 +1,1169 @@
+use std::{error::Error, fmt};
+
+use encoding_rs::Encoding;
+
+use crate::{
+    printer::{ColorChoice, OutputFormat},
+    query::{self, ParseError},
+    unicode::Normalization,
+    BinaryFiles, Config, Context, Mode,
+};
+
+/// `ConfigError` enum and implementations
+#[derive(Debug, PartialEq, Eq)]
+pub enum ConfigError {
+    /// No query was given.
+    MissingQuery,
+    /// An argument starting with `-` that isn't a known flag.
+    UnknownFlag(String),
+    /// A flag that takes a value was the last argument.
+    MissingValue(String),
+    /// A value was given with `=` to a flag that doesn't take one.
+    UnexpectedValue(String),
+    /// A flag's value couldn't be parsed.
+    InvalidValue { flag: String, value: String },
+    /// A flag was used without another flag it depends on.
+    Requires {
+        flag: String,
+        requires: &'static str,
+    },
+    /// Two flags that can't be combined were both used.
+    Conflicts { flag: String, with: &'static str },
+    /// The expression given to `--query-expr` couldn't be parsed.
+    InvalidQuery { expr: String, error: ParseError },
+    /// `minigrep index` was given something other than `build <DIR>...`.
+    IndexUsage,
+    /// `--help` was given; displays the usage text.
+    Help,
+    /// `--version` was given; displays the version.
+    Version,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::MissingQuery => write!(f, "Didn't get a query string"),
+            ConfigError::UnknownFlag(flag) => write!(f, "Unknown flag '{flag}'"),
+            ConfigError::MissingValue(flag) => write!(f, "Flag '{flag}' needs a value"),
+            ConfigError::UnexpectedValue(flag) => write!(f, "Flag '{flag}' doesn't take a value"),
+            ConfigError::InvalidValue { flag, value } => {
+                write!(f, "Invalid value '{value}' for flag '{flag}'")
+            }
+            ConfigError::Requires { flag, requires } => {
+                write!(f, "Flag '{flag}' can only be used with '{requires}'")
+            }
+            ConfigError::Conflicts { flag, with } => {
+                write!(f, "Flag '{flag}' can't be used with '{with}'")
+            }
+            ConfigError::InvalidQuery { expr, error } => {
+                // point at the column under the expression itself
+                let caret = format!("{:>width$}", "^", width = error.column);
+                write!(f, "Invalid query expression: {error}\n  {expr}\n  {caret}")
+            }
+            ConfigError::IndexUsage => write!(f, "Usage: minigrep index build <DIR>..."),
+            ConfigError::Help => write!(f, "{}", usage()),
+            ConfigError::Version => write!(f, "minigrep {}", env!("CARGO_PKG_VERSION")),
+        }
+    }
+}
+
+impl Error for ConfigError {}
+
+#[derive(Debug, Clone, Copy)]
+enum Opt {
+    IgnoreCase,
+    CaseSensitive,
+    Normalize,
+    Regex,
+    Multiline,
+    Fuzzy,
+    SortDistance,
+    Pattern,
+    PatternFile,
+    QueryExpr,
+    Format,
+    Replace,
+    InPlace,
+    DryRun,
+    Glob,
+    NoIgnore,
+    Hidden,
+    NoIndex,
+    Follow,
+    Threads,
+    SortFiles,
+    LineNumber,
+    ByteOffset,
+    AfterContext,
+    BeforeContext,
+    Context,
+    Invert,
+    Count,
+    FilesWithMatches,
+    FilesWithoutMatch,
+    MaxCount,
+    Text,
+    SkipBinary,
+    Encoding,
+    Color,
+    Json,
+    Help,
+    Version,
+}
+
+struct Flag {
+    opt: Opt,
+    short: Option<char>,
+    long: &'static str,
+    /// Name of the flag's value in the usage text, or `None` for a switch.
+    value: Option<&'static str>,
+    help: &'static str,
+}
+
+impl Flag {
+    /// Can the value be left out? It then has to be given inline, as in `--in-place=.bak`.
+    fn optional_value(&self) -> bool {
+        matches!(self.opt, Opt::InPlace)
+    }
+}
+
+// every flag minigrep understands; the usage text is generated from this table
+const FLAGS: &[Flag] = &[
+    Flag {
+        opt: Opt::IgnoreCase,
+        short: Some('i'),
+        long: "ignore-case",
+        value: None,
+        help: "Match case-insensitively",
+    },
+    Flag {
+        opt: Opt::CaseSensitive,
+        short: Some('s'),
+        long: "case-sensitive",
+        value: None,
+        help: "Match case-sensitively, even if IGNORE_CASE is set",
+    },
+    Flag {
+        opt: Opt::Normalize,
+        short: None,
+        long: "normalize",
+        value: Some("FORM"),
+        help: "Normalize text before matching: nfc, or nfkc to also match compatibility forms",
+    },
+    Flag {
+        opt: Opt::Regex,
+        short: Some('E'),
+        long: "regex",
+        value: None,
+        help: "Treat QUERY as a regular expression",
+    },
+    Flag {
+        opt: Opt::Multiline,
+        short: Some('U'),
+        long: "multiline",
+        value: None,
+        help: "Let matches span lines, printing every line they touch",
+    },
+    Flag {
+        opt: Opt::Fuzzy,
+        short: None,
+        long: "fuzzy",
+        value: Some("NUM"),
+        help: "Match QUERY with up to NUM inserted, deleted or substituted characters",
+    },
+    Flag {
+        opt: Opt::SortDistance,
+        short: None,
+        long: "sort-distance",
+        value: None,
+        help: "Print fuzzy matches closest first, after searching every file",
+    },
+    Flag {
+        opt: Opt::Pattern,
+        short: Some('e'),
+        long: "regexp",
+        value: Some("PATTERN"),
+        help: "Search for PATTERN; repeat to search for any of several patterns",
+    },
+    Flag {
+        opt: Opt::PatternFile,
+        short: Some('f'),
+        long: "file",
+        value: Some("FILE"),
+        help: "Search for the patterns in FILE, one per line",
+    },
+    Flag {
+        opt: Opt::QueryExpr,
+        short: None,
+        long: "query-expr",
+        value: Some("EXPR"),
+        help: "Select lines by a boolean expression of terms instead of a QUERY",
+    },
+    Flag {
+        opt: Opt::Format,
+        short: None,
+        long: "format",
+        value: Some("TEMPLATE"),
+        help: "Print TEMPLATE for each match; $1 or ${name} insert capture groups (needs --regex)",
+    },
+    Flag {
+        opt: Opt::Replace,
+        short: Some('r'),
+        long: "replace",
+        value: Some("TEMPLATE"),
+        help: "Print matching lines with each match replaced by TEMPLATE; regexes expand $1",
+    },
+    Flag {
+        opt: Opt::InPlace,
+        short: None,
+        long: "in-place",
+        value: Some("SUFFIX"),
+        help: "Rewrite files with the replacements, backing them up with SUFFIX if given",
+    },
+    Flag {
+        opt: Opt::DryRun,
+        short: None,
+        long: "dry-run",
+        value: None,
+        help: "Print a diff of what --in-place would change, without changing anything",
+    },
+    Flag {
+        opt: Opt::Glob,
+        short: Some('g'),
+        long: "glob",
+        value: Some("GLOB"),
+        help: "Only search walked files matching GLOB; a leading ! excludes instead",
+    },
+    Flag {
+        opt: Opt::NoIgnore,
+        short: None,
+        long: "no-ignore",
+        value: None,
+        help: "Search files excluded by .gitignore, .ignore and .git/info/exclude",
+    },
+    Flag {
+        opt: Opt::Hidden,
+        short: None,
+        long: "hidden",
+        value: None,
+        help: "Search hidden files and directories",
+    },
+    Flag {
+        opt: Opt::NoIndex,
+        short: None,
+        long: "no-index",
+        value: None,
+        help: "Search every file, even in a directory with an index",
+    },
+    Flag {
+        opt: Opt::Follow,
+        short: None,
+        long: "follow",
+        value: None,
+        help: "Keep searching files as lines are appended to them, like tail -f",
+    },
+    Flag {
+        opt: Opt::Threads,
+        short: Some('j'),
+        long: "threads",
+        value: Some("NUM"),
+        help: "Search NUM files at a time on as many threads",
+    },
+    Flag {
+        opt: Opt::SortFiles,
+        short: None,
+        long: "sort-files",
+        value: None,
+        help: "Print files in the order of their paths, even with -j",
+    },
+    Flag {
+        opt: Opt::LineNumber,
+        short: Some('n'),
+        long: "line-number",
+        value: None,
+        help: "Prefix each line with its line number",
+    },
+    Flag {
+        opt: Opt::ByteOffset,
+        short: Some('b'),
+        long: "byte-offset",
+        value: None,
+        help: "Prefix each line with its byte offset",
+    },
+    Flag {
+        opt: Opt::AfterContext,
+        short: Some('A'),
+        long: "after-context",
+        value: Some("NUM"),
+        help: "Print NUM lines after each match",
+    },
+    Flag {
+        opt: Opt::BeforeContext,
+        short: Some('B'),
+        long: "before-context",
+        value: Some("NUM"),
+        help: "Print NUM lines before each match",
+    },
+    Flag {
+        opt: Opt::Context,
+        short: Some('C'),
+        long: "context",
+        value: Some("NUM"),
+        help: "Print NUM lines before and after each match",
+    },
+    Flag {
+        opt: Opt::Invert,
+        short: Some('v'),
+        long: "invert-match",
+        value: None,
+        help: "Select the lines that don't match",
+    },
+    Flag {
+        opt: Opt::Count,
+        short: Some('c'),
+        long: "count",
+        value: None,
+        help: "Print the number of selected lines in each file",
+    },
+    Flag {
+        opt: Opt::FilesWithMatches,
+        short: Some('l'),
+        long: "files-with-matches",
+        value: None,
+        help: "Print only the paths of files with a selected line",
+    },
+    Flag {
+        opt: Opt::FilesWithoutMatch,
+        short: Some('L'),
+        long: "files-without-match",
+        value: None,
+        help: "Print only the paths of files without a selected line",
+    },
+    Flag {
+        opt: Opt::MaxCount,
+        short: Some('m'),
+        long: "max-count",
+        value: Some("NUM"),
+        help: "Stop searching a file after NUM selected lines",
+    },
+    Flag {
+        opt: Opt::Text,
+        short: Some('a'),
+        long: "text",
+        value: None,
+        help: "Search binary files as if they were text",
+    },
+    Flag {
+        opt: Opt::SkipBinary,
+        short: Some('I'),
+        long: "skip-binary",
+        value: None,
+        help: "Skip binary files instead of reporting that they match",
+    },
+    Flag {
+        opt: Opt::Encoding,
+        short: None,
+        long: "encoding",
+        value: Some("LABEL"),
+        help: "Decode input from LABEL, e.g. latin1 or windows-1252, instead of UTF-8",
+    },
+    Flag {
+        opt: Opt::Color,
+        short: None,
+        long: "color",
+        value: Some("WHEN"),
+        help: "Highlight matches: always, never or auto (only on a terminal, the default)",
+    },
+    Flag {
+        opt: Opt::Json,
+        short: None,
+        long: "json",
+        value: None,
+        help: "Print results as JSON Lines",
+    },
+    Flag {
+        opt: Opt::Help,
+        short: Some('h'),
+        long: "help",
+        value: None,
+        help: "Print this help and exit",
+    },
+    Flag {
+        opt: Opt::Version,
+        short: Some('V'),
+        long: "version",
+        value: None,
+        help: "Print the version and exit",
+    },
+];
+
+/// The `--help` text, generated from the flag table.
+pub fn usage() -> String {
+    let mut usage = String::from(
+        "Usage: minigrep [OPTIONS] <QUERY> [PATH]...\n\
+         \x20      minigrep [OPTIONS] -e <PATTERN>... [PATH]...\n\
+         \x20      minigrep [OPTIONS] --query-expr <EXPR> [PATH]...\n\
+         \x20      minigrep index build <DIR>...\n\
+         \n\
+         Search for QUERY in each PATH. Directories are searched recursively, skipping\n\
+         hidden and ignored files and links to directories. Standard input is read when\n\
+         PATH is '-' or missing.\n\
+         \n\
+         With -e or -f, every argument is a PATH and a line is selected when any of the\n\
+         patterns matches it.\n\
+         \n\
+         --query-expr combines terms with AND, OR, NOT and parentheses, as in\n\
+         'timeout AND (db OR cache) NOT retry'. Terms side by side are combined with AND,\n\
+         and \"quoted phrases\" can hold spaces. With -E, every term is a regex. Like -e,\n\
+         it makes every argument a PATH.\n\
+         \n\
+         With -U, the whole input is searched at once, so a match can span lines, and each\n\
+         match is printed with every line it touches. In a regex, \\n matches a line break\n\
+         and ^ and $ still match at the start and end of every line.\n\
+         \n\
+         'minigrep index build' writes a trigram index into each DIR. Literal searches of\n\
+         an indexed directory skip the files it rules out, and update it when files change.\n\
+         \n\
+         With --follow, minigrep keeps waiting for lines appended to each file until it is\n\
+         interrupted. A file that is truncated or replaced is searched again from its start,\n\
+         and its line numbers carry on from where they were.\n\
+         \n\
+         With -j, several files are searched at once. The lines of each file are still\n\
+         printed together, but files are printed as they finish unless --sort-files is\n\
+         given too.\n\
+         \n\
+         Case-insensitivity comes from the last of -i/-s on the command line. Without\n\
+         either, it is enabled when the IGNORE_CASE environment variable is set.\n\
+         \n\
+         Options:\n",
+    );
+
+    let columns: Vec<String> = FLAGS
+        .iter()
+        .map(|flag| {
+            let short = match flag.short {
+                Some(short) => format!("-{short}, "),
+                None => String::from("    "),
+            };
+            let value = match flag.value {
+                Some(value) if flag.optional_value() => format!("[=<{value}>]"),
+                Some(value) => format!(" <{value}>"),
+                None => String::new(),
+            };
+            format!("{short}--{}{value}", flag.long)
+        })
+        .collect();
+
+    let width = columns.iter().map(String::len).max().unwrap_or(0);
+
+    for (column, flag) in columns.iter().zip(FLAGS) {
+        usage.push_str(&format!("  {column:width$}  {}\n", flag.help));
+    }
+
+    let rest = "    --";
+    usage.push_str(&format!(
+        "  {rest:width$}  Treat every following argument as QUERY or PATH"
+    ));
+    usage
+}
+
+/// Whether command-line arguments, program name first, run `minigrep index build` instead of a
+/// search. Other searches for the word `index`, like `minigrep index src/`, are left alone.
+pub fn is_index_command(args: &[String]) -> bool {
+    args.get(1).is_some_and(|arg| arg == "index") && args.get(2).is_some_and(|arg| arg == "build")
+}
+
+/// Parse command-line arguments, program name first, into a `Config`.
+///
+/// `ignore_case_env` is the fallback used when neither `-i` nor `-s` is given.
+pub fn parse(
+    mut args: impl Iterator<Item = String>,
+    ignore_case_env: bool,
+) -> Result<Config, ConfigError> {
+    args.next();
+
+    let mut config = Config::default();
+    let mut ignore_case = None;
+    let mut positional = Vec::new();
+
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            positional.extend(args.by_ref());
+            break;
+        }
+
+        if let Some(long) = arg.strip_prefix("--") {
+            let (name, inline) = match long.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_string())),
+                None => (long, None),
+            };
+
+            let flag = FLAGS
+                .iter()
+                .find(|flag| flag.long == name)
+                .ok_or_else(|| ConfigError::UnknownFlag(format!("--{name}")))?;
+
+            let value = match (flag.value, inline) {
+                (Some(_), Some(value)) => Some(value),
+                (Some(_), None) if flag.optional_value() => None,
+                (Some(_), None) => Some(
+                    args.next()
+                        .ok_or_else(|| ConfigError::MissingValue(arg.clone()))?,
+                ),
+                (None, Some(_)) => return Err(ConfigError::UnexpectedValue(format!("--{name}"))),
+                (None, None) => None,
+            };
+
+            apply(
+                &mut config,
+                &mut ignore_case,
+                flag,
+                &format!("--{name}"),
+                value,
+            )?;
+        } else if arg.len() > 1 && arg.starts_with('-') {
+            // short flags can be combined, as in `-in`, and the last may take its value inline, as
+            // in `-nA2`
+            for (i, short) in arg.char_indices().skip(1) {
+                let name = format!("-{short}");
+                let flag = FLAGS
+                    .iter()
+                    .find(|flag| flag.short == Some(short))
+                    .ok_or_else(|| ConfigError::UnknownFlag(name.clone()))?;
+
+                if flag.value.is_none() {
+                    apply(&mut config, &mut ignore_case, flag, &name, None)?;
+                    continue;
+                }
+
+                let rest = &arg[i + short.len_utf8()..];
+                let value = if rest.is_empty() {
+                    args.next()
+                        .ok_or_else(|| ConfigError::MissingValue(name.clone()))?
+                } else {
+                    rest.to_string()
+                };
+
+                apply(&mut config, &mut ignore_case, flag, &name, Some(value))?;
+                break;
+            }
+        } else {
+            positional.push(arg);
+        }
+    }
+
+    let mut positional = positional.into_iter();
+
+    // like grep, `-e` and `-f` replace the query, so the first argument is already a path
+    let replaced = !config.patterns.is_empty() || !config.pattern_files.is_empty();
+    if !replaced && config.query_expr.is_none() {
+        config.query = positional.next().ok_or(ConfigError::MissingQuery)?;
+    }
+    config.paths = positional.collect();
+
+    // with no path, read from stdin so minigrep can sit at the end of a pipe
+    if config.paths.is_empty() {
+        config.paths.push(String::from("-"));
+    }
+
+    if config.replace.is_none() {
+        let needs_replace = [
+            (config.in_place, "--in-place"),
+            (config.dry_run, "--dry-run"),
+        ];
+
+        if let Some((_, flag)) = needs_replace.iter().find(|(used, _)| *used) {
+            return Err(ConfigError::Requires {
+                flag: flag.to_string(),
+                requires: "--replace",
+            });
+        }
+    }
+
+    if config.sort_distance && config.fuzzy.is_none() {
+        return Err(ConfigError::Requires {
+            flag: String::from("--sort-distance"),
+            requires: "--fuzzy",
+        });
+    }
+
+    if config.fuzzy.is_some() {
+        let conflicts = [
+            (config.regex, "--regex"),
+            (!config.patterns.is_empty(), "--regexp"),
+            (!config.pattern_files.is_empty(), "--file"),
+            (config.query_expr.is_some(), "--query-expr"),
+        ];
+
+        if let Some((_, with)) = conflicts.iter().find(|(used, _)| *used) {
+            return Err(ConfigError::Conflicts {
+                flag: String::from("--fuzzy"),
+                with,
+            });
+        }
+    }
+
+    if config.query_expr.is_some() {
+        let conflicts = [
+            (!config.patterns.is_empty(), "--regexp"),
+            (!config.pattern_files.is_empty(), "--file"),
+        ];
+
+        if let Some((_, with)) = conflicts.iter().find(|(used, _)| *used) {
+            return Err(ConfigError::Conflicts {
+                flag: String::from("--query-expr"),
+                with,
+            });
+        }
+    }
+
+    if config.multiline {
+        let conflicts = [
+            (config.invert, "--invert-match"),
+            (!config.context.is_empty(), "--context"),
+            (config.fuzzy.is_some(), "--fuzzy"),
+            (config.query_expr.is_some(), "--query-expr"),
+            (config.replace.is_some(), "--replace"),
+            (config.follow, "--follow"),
+        ];
+
+        if let Some((_, with)) = conflicts.iter().find(|(used, _)| *used) {
+            return Err(ConfigError::Conflicts {
+                flag: String::from("--multiline"),
+                with,
+            });
+        }
+    }
+
+    if config.follow {
+        let conflicts = [
+            (config.mode == Mode::Count, "--count"),
+            (
+                config.mode == Mode::FilesWithMatches,
+                "--files-with-matches",
+            ),
+            (
+                config.mode == Mode::FilesWithoutMatch,
+                "--files-without-match",
+            ),
+            (config.sort_distance, "--sort-distance"),
+            (config.threads.is_some(), "--threads"),
+            (config.in_place, "--in-place"),
+            (config.dry_run, "--dry-run"),
+        ];
+
+        if let Some((_, with)) = conflicts.iter().find(|(used, _)| *used) {
+            return Err(ConfigError::Conflicts {
+                flag: String::from("--follow"),
+                with,
+            });
+        }
+    }
+
+    // every match in a file is rewritten, so flags that choose lines or print something else
+    // would be ignored
+    if config.in_place || config.dry_run {
+        let conflicts = [
+            (config.invert, "--invert-match"),
+            (config.max_count.is_some(), "--max-count"),
+            (config.mode == Mode::Count, "--count"),
+            (
+                config.mode == Mode::FilesWithMatches,
+                "--files-with-matches",
+            ),
+            (
+                config.mode == Mode::FilesWithoutMatch,
+                "--files-without-match",
+            ),
+        ];
+        let flag = if config.in_place {
+            "--in-place"
+        } else {
+            "--dry-run"
+        };
+
+        if let Some((_, with)) = conflicts.iter().find(|(used, _)| *used) {
+            return Err(ConfigError::Conflicts {
+                flag: String::from(flag),
+                with,
+            });
+        }
+    }
+
+    // lines sorted by distance are only printed once every file has been searched anyway
+    if config.sort_distance && config.threads.is_some() {
+        return Err(ConfigError::Conflicts {
+            flag: String::from("--sort-distance"),
+            with: "--threads",
+        });
+    }
+
+    if config.format.is_some() && !config.regex {
+        return Err(ConfigError::Requires {
+            flag: String::from("--format"),
+            requires: "--regex",
+        });
+    }
+
+    // a flag always wins over the environment variable
+    config.ignore_case = ignore_case.unwrap_or(ignore_case_env);
+
+    Ok(config)
+}
+
+fn apply(
+    config: &mut Config,
+    ignore_case: &mut Option<bool>,
+    flag: &Flag,
+    name: &str,
+    value: Option<String>,
+) -> Result<(), ConfigError> {
+    let count = |value: Option<String>| -> Result<usize, ConfigError> {
+        let value = value.unwrap_or_default();
+        value.parse().map_err(|_| ConfigError::InvalidValue {
+            flag: name.to_string(),
+            value,
+        })
+    };
+
+    match flag.opt {
+        Opt::IgnoreCase => *ignore_case = Some(true),
+        Opt::CaseSensitive => *ignore_case = Some(false),
+        Opt::Normalize => {
+            config.normalization = match value.as_deref() {
+                Some("nfc") => Some(Normalization::Nfc),
+                Some("nfkc") => Some(Normalization::Nfkc),
+                _ => {
+                    return Err(ConfigError::InvalidValue {
+                        flag: name.to_string(),
+                        value: value.unwrap_or_default(),
+                    })
+                }
+            };
+        }
+        Opt::Regex => config.regex = true,
+        Opt::Multiline => config.multiline = true,
+        Opt::Fuzzy => config.fuzzy = Some(count(value)?),
+        Opt::SortDistance => config.sort_distance = true,
+        Opt::Pattern => config.patterns.extend(value),
+        Opt::PatternFile => config.pattern_files.extend(value),
+        Opt::QueryExpr => {
+            let expr = value.unwrap_or_default();
+            let parsed =
+                query::parse(&expr).map_err(|error| ConfigError::InvalidQuery { expr, error })?;
+            config.query_expr = Some(parsed);
+        }
+        Opt::Format => config.format = value,
+        Opt::Replace => config.replace = value,
+        Opt::InPlace => {
+            config.in_place = true;
+            config.backup_suffix = value.filter(|suffix| !suffix.is_empty());
+        }
+        Opt::DryRun => config.dry_run = true,
+        Opt::Glob => config.globs.extend(value),
+        Opt::NoIgnore => config.no_ignore = true,
+        Opt::Hidden => config.hidden = true,
+        Opt::NoIndex => config.no_index = true,
+        Opt::Follow => config.follow = true,
+        Opt::Threads => {
+            let threads = count(value)?;
+            if threads == 0 {
+                return Err(ConfigError::InvalidValue {
+                    flag: name.to_string(),
+                    value: threads.to_string(),
+                });
+            }
+            config.threads = Some(threads);
+        }
+        Opt::SortFiles => config.sort_files = true,
+        Opt::LineNumber => config.line_number = true,
+        Opt::ByteOffset => config.byte_offset = true,
+        Opt::AfterContext => config.context.after = count(value)?,
+        Opt::BeforeContext => config.context.before = count(value)?,
+        Opt::Context => {
+            let lines = count(value)?;
+            config.context = Context {
+                before: lines,
+                after: lines,
+            };
+        }
+        Opt::Invert => config.invert = true,
+        Opt::Count => config.mode = Mode::Count,
+        Opt::FilesWithMatches => config.mode = Mode::FilesWithMatches,
+        Opt::FilesWithoutMatch => config.mode = Mode::FilesWithoutMatch,
+        Opt::MaxCount => config.max_count = Some(count(value)?),
+        Opt::Text => config.binary = BinaryFiles::Text,
+        Opt::SkipBinary => config.binary = BinaryFiles::Skip,
+        Opt::Encoding => {
+            let label = value.unwrap_or_default();
+            config.encoding = Some(Encoding::for_label(label.as_bytes()).ok_or_else(|| {
+                ConfigError::InvalidValue {
+                    flag: name.to_string(),
+                    value: label,
+                }
+            })?);
+        }
+        Opt::Color => {
+            config.color = match value.as_deref() {
+                Some("always") => ColorChoice::Always,
+                Some("never") => ColorChoice::Never,
+                Some("auto") => ColorChoice::Auto,
+                _ => {
+                    return Err(ConfigError::InvalidValue {
+                        flag: name.to_string(),
+                        value: value.unwrap_or_default(),
+                    })
+                }
+            };
+        }
+        Opt::Json => config.output = OutputFormat::Json,
+        Opt::Help => return Err(ConfigError::Help),
+        Opt::Version => return Err(ConfigError::Version),
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use encoding_rs::WINDOWS_1252;
+
+    fn parse_args(args: &[&str], ignore_case_env: bool) -> Result<Config, ConfigError> {
+        let args = ["minigrep"].iter().chain(args).map(|arg| arg.to_string());
+        parse(args, ignore_case_env)
+    }
+
+    #[test]
+    fn searching_for_the_word_index() {
+        let args = |args: &[&str]| -> Vec<String> {
+            ["minigrep"]
+                .iter()
+                .chain(args)
+                .map(|arg| arg.to_string())
+                .collect()
+        };
+
+        assert!(is_index_command(&args(&["index", "build", "src"])));
+        assert!(!is_index_command(&args(&["index", "src/"])));
+        assert!(!is_index_command(&args(&["index"])));
+
+        let config = parse_args(&["index", "src/"], false).unwrap();
+        assert_eq!("index", config.query);
+        assert_eq!(vec!["src/"], config.paths);
+    }
+
+    #[test]
+    fn combined_short_flags() {
+        let config = parse_args(&["-inA2", "rust", "poem.txt"], false).unwrap();
+
+        assert!(config.ignore_case);
+        assert!(config.line_number);
+        assert_eq!(2, config.context.after);
+        assert_eq!("rust", config.query);
+        assert_eq!(vec!["poem.txt"], config.paths);
+    }
+
+    #[test]
+    fn long_flags_with_values() {
+        let config = parse_args(
+            &[
+                "--glob=*.rs",
+                "--glob",
+                "!target/**",
+                "-C",
+                "1",
+                "fn",
+                "src",
+            ],
+            false,
+        )
+        .unwrap();
+
+        assert_eq!(vec!["*.rs", "!target/**"], config.globs);
+        assert_eq!(
+            Context {
+                before: 1,
+                after: 1
+            },
+            config.context
+        );
+        assert_eq!(OutputFormat::Standard, config.output);
+    }
+
+    #[test]
+    fn repeated_patterns() {
+        let config = parse_args(
+            &[
+                "-e",
+                "rust",
+                "--regexp=trust",
+                "-f",
+                "words.txt",
+                "poem.txt",
+            ],
+            false,
+        )
+        .unwrap();
+
+        assert_eq!(vec!["rust", "trust"], config.patterns);
+        assert_eq!(vec!["words.txt"], config.pattern_files);
+        assert_eq!("", config.query);
+        assert_eq!(vec!["poem.txt"], config.paths);
+    }
+
+    #[test]
+    fn query_expression() {
+        let config = parse_args(&["--query-expr", "db NOT retry", "app.log"], false).unwrap();
+
+        assert_eq!(
+            vec!["db", "retry"],
+            config.query_expr.as_ref().unwrap().terms()
+        );
+        assert_eq!(vec!["app.log"], config.paths);
+
+        let error = parse_args(&["--query-expr=(db OR", "app.log"], false).unwrap_err();
+        assert_eq!(
+            "Invalid query expression: expected a term at column 7\n  (db OR\n        ^",
+            error.to_string()
+        );
+    }
+
+    #[test]
+    fn multiline() {
+        assert!(
+            parse_args(&["-UE", r"fn \w+\(\n", "src"], false)
+                .unwrap()
+                .multiline
+        );
+        assert_eq!(
+            Err(ConfigError::Conflicts {
+                flag: String::from("--multiline"),
+                with: "--context",
+            }),
+            parse_args(&["--multiline", "-C2", "fn"], false).map(|_| ())
+        );
+    }
+
+    #[test]
+    fn walk_switches() {
+        let config = parse_args(&["--no-ignore", "--hidden", "rust", "."], false).unwrap();
+
+        assert!(config.no_ignore);
+        assert!(config.hidden);
+    }
+
+    #[test]
+    fn binary_files_and_encoding() {
+        let config = parse_args(&["-aI", "--encoding", "latin1", "rust"], false).unwrap();
+
+        // the last of -a/-I wins, and latin1 is decoded as its superset windows-1252
+        assert_eq!(BinaryFiles::Skip, config.binary);
+        assert_eq!(Some(WINDOWS_1252), config.encoding);
+        assert_eq!(
+            Err(ConfigError::InvalidValue {
+                flag: String::from("--encoding"),
+                value: String::from("klingon"),
+            }),
+            parse_args(&["--encoding=klingon", "rust"], false).map(|_| ())
+        );
+    }
+
+    #[test]
+    fn replace_in_place() {
+        let config = parse_args(&["-r", "Rust", "--in-place", "rust", "src"], false).unwrap();
+
+        assert_eq!(Some(String::from("Rust")), config.replace);
+        assert!(config.in_place);
+        assert_eq!(None, config.backup_suffix);
+        // without `=`, the next argument is not taken as the suffix
+        assert_eq!("rust", config.query);
+
+        let config = parse_args(&["-r", "Rust", "--in-place=.bak", "rust"], false).unwrap();
+        assert_eq!(Some(String::from(".bak")), config.backup_suffix);
+
+        assert_eq!(
+            Err(ConfigError::Requires {
+                flag: String::from("--dry-run"),
+                requires: "--replace",
+            }),
+            parse_args(&["--dry-run", "rust"], false).map(|_| ())
+        );
+
+        for (flag, with) in [
+            ("-v", "--invert-match"),
+            ("-m1", "--max-count"),
+            ("-c", "--count"),
+            ("-l", "--files-with-matches"),
+            ("-L", "--files-without-match"),
+        ] {
+            assert_eq!(
+                Err(ConfigError::Conflicts {
+                    flag: String::from("--in-place"),
+                    with,
+                }),
+                parse_args(&[flag, "-r", "X", "--in-place", "foo"], false).map(|_| ())
+            );
+        }
+        assert_eq!(
+            Err(ConfigError::Conflicts {
+                flag: String::from("--dry-run"),
+                with: "--invert-match",
+            }),
+            parse_args(&["-v", "-r", "X", "--dry-run", "foo"], false).map(|_| ())
+        );
+    }
+
+    #[test]
+    fn fuzzy_distance() {
+        let config = parse_args(&["--fuzzy=2", "--sort-distance", "-i", "rust"], false).unwrap();
+
+        assert_eq!(Some(2), config.fuzzy);
+        assert!(config.sort_distance);
+        assert_eq!(
+            Err(ConfigError::Conflicts {
+                flag: String::from("--fuzzy"),
+                with: "--regex",
+            }),
+            parse_args(&["--fuzzy", "1", "-E", "rust"], false).map(|_| ())
+        );
+    }
+
+    #[test]
+    fn threads() {
+        let config = parse_args(&["-j8", "--sort-files", "fn", "src"], false).unwrap();
+
+        assert_eq!(Some(8), config.threads);
+        assert!(config.sort_files);
+        assert_eq!(
+            Err(ConfigError::InvalidValue {
+                flag: String::from("--threads"),
+                value: String::from("0"),
+            }),
+            parse_args(&["--threads=0", "fn"], false).map(|_| ())
+        );
+    }
+
+    #[test]
+    fn follow_only_prints_lines() {
+        assert!(
+            parse_args(&["--follow", "error", "app.log"], false)
+                .unwrap()
+                .follow
+        );
+        assert_eq!(
+            Err(ConfigError::Conflicts {
+                flag: String::from("--follow"),
+                with: "--count",
+            }),
+            parse_args(&["--follow", "-c", "error", "app.log"], false).map(|_| ())
+        );
+    }
+
+    #[test]
+    fn selection_modes() {
+        let config = parse_args(&["-vc", "-m", "3", "fn"], false).unwrap();
+
+        assert!(config.invert);
+        assert_eq!(Mode::Count, config.mode);
+        assert_eq!(Some(3), config.max_count);
+        assert_eq!(
+            Mode::FilesWithoutMatch,
+            parse_args(&["-L", "fn"], false).unwrap().mode
+        );
+    }
+
+    #[test]
+    fn color_choice() {
+        assert_eq!(ColorChoice::Auto, parse_args(&["fn"], false).unwrap().color);
+        assert_eq!(
+            ColorChoice::Never,
+            parse_args(&["--color=never", "fn"], false).unwrap().color
+        );
+        assert_eq!(
+            Err(ConfigError::InvalidValue {
+                flag: String::from("--color"),
+                value: String::from("sometimes")
+            }),
+            parse_args(&["--color", "sometimes", "fn"], false).map(|_| ())
+        );
+    }
+
+    #[test]
+    fn normalization_form() {
+        assert_eq!(None, parse_args(&["fn"], false).unwrap().normalization);
+        assert_eq!(
+            Some(Normalization::Nfkc),
+            parse_args(&["--normalize=nfkc", "fn"], false)
+                .unwrap()
+                .normalization
+        );
+    }
+
+    #[test]
+    fn json_output() {
+        let config = parse_args(&["--json", "fn"], false).unwrap();
+
+        assert_eq!(OutputFormat::Json, config.output);
+    }
+
+    #[test]
+    fn double_dash_ends_flags() {
+        let config = parse_args(&["--", "-n", "-"], false).unwrap();
+
+        assert_eq!("-n", config.query);
+        assert!(!config.line_number);
+        assert_eq!(vec!["-"], config.paths);
+    }
+
+    #[test]
+    fn flags_take_precedence_over_env() {
+        assert!(parse_args(&["rust"], true).unwrap().ignore_case);
+        assert!(!parse_args(&["-s", "rust"], true).unwrap().ignore_case);
+        assert!(
+            parse_args(&["-s", "-i", "rust"], false)
+                .unwrap()
+                .ignore_case
+        );
+    }
+
+    #[test]
+    fn errors_name_the_argument() {
+        assert_eq!(
+            Err(ConfigError::UnknownFlag(String::from("-x"))),
+            parse_args(&["-nx", "rust"], false).map(|_| ())
+        );
+        assert_eq!(
+            Err(ConfigError::MissingValue(String::from("--context"))),
+            parse_args(&["rust", "--context"], false).map(|_| ())
+        );
+        assert_eq!(
+            Err(ConfigError::UnexpectedValue(String::from("--regex"))),
+            parse_args(&["--regex=yes", "rust"], false).map(|_| ())
+        );
+        assert_eq!(
+            Err(ConfigError::InvalidValue {
+                flag: String::from("-A"),
+                value: String::from("two")
+            }),
+            parse_args(&["-A", "two", "rust"], false).map(|_| ())
+        );
+        assert_eq!(
+            Err(ConfigError::MissingQuery),
+            parse_args(&["-n"], false).map(|_| ())
+        );
+    }
+
+    #[test]
+    fn help_and_version() {
+        assert_eq!(
+            Err(ConfigError::Help),
+            parse_args(&["rust", "--help"], false).map(|_| ())
+        );
+        assert_eq!(
+            Err(ConfigError::Version),
+            parse_args(&["-V"], false).map(|_| ())
+        );
+        assert!(usage().contains("  -i, --ignore-case "));
+    }
+}
@@ -0,0 +1,170 @@
+use std::{
+    fs::{self, File, Metadata},
+    io::{self, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+    time::Duration,
+};
+
+/// How long `FollowReader` waits before looking for more input.
+pub const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// `FollowReader` struct and implementations
+///
+/// Reads a file like `tail -f`: at its end, it waits for more to be appended instead of returning
+/// end of file. A file truncated below what was already read is read again from its start, and
+/// when the path names a different file, as after log rotation, the new file is read from its
+/// start. End of file is only returned once `stop` is set and everything has been read.
+pub struct FollowReader<'a> {
+    path: PathBuf,
+    file: File,
+    /// Bytes read from `file` so far.
+    position: u64,
+    id: Option<(u64, u64)>,
+    stop: &'a AtomicBool,
+    interval: Duration,
+}
+
+impl<'a> FollowReader<'a> {
+    pub fn open(path: &Path, stop: &'a AtomicBool, interval: Duration) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let id = file_id(&file.metadata()?);
+
+        Ok(FollowReader {
+            path: path.to_path_buf(),
+            file,
+            position: 0,
+            id,
+            stop,
+            interval,
+        })
+    }
+
+    /// Switch to reading from the start if the file was truncated or replaced. Returns whether
+    /// there may be more to read now.
+    fn reopen(&mut self) -> io::Result<bool> {
+        // between a rotation's rename and the new file's creation, nothing is there yet
+        let Ok(metadata) = fs::metadata(&self.path) else {
+            return Ok(false);
+        };
+
+        if file_id(&metadata) != self.id {
+            // whatever was written to the old file before it was replaced is read first
+            if self.file.metadata()?.len() > self.position {
+                return Ok(true);
+            }
+
+            let Ok(file) = File::open(&self.path) else {
+                return Ok(false);
+            };
+            self.id = file_id(&file.metadata()?);
+            self.file = file;
+            self.position = 0;
+            return Ok(true);
+        }
+
+        if metadata.len() < self.position {
+            self.file.seek(SeekFrom::Start(0))?;
+            self.position = 0;
+            return Ok(true);
+        }
+
+        Ok(false)
+    }
+}
+
+impl Read for FollowReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let read = self.file.read(buf)?;
+            if read > 0 {
+                self.position += read as u64;
+                return Ok(read);
+            }
+
+            if self.reopen()? {
+                continue;
+            }
+
+            if self.stop.load(Ordering::Relaxed) {
+                return Ok(0);
+            }
+
+            thread::sleep(self.interval);
+        }
+    }
+}
+
+/// The device and inode of a file, which change when a path is pointed at a new file.
+#[cfg(unix)]
+fn file_id(metadata: &Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+
+    Some((metadata.dev(), metadata.ino()))
+}
+
+// elsewhere only truncation is noticed
+#[cfg(not(unix))]
+fn file_id(_metadata: &Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{matcher::find_case_sensitive, search_reader, SearchLine, SearchOptions};
+    use std::{fs::OpenOptions, io::BufReader, io::Write};
+
+    #[test]
+    fn appends_truncation_and_rotation() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("app.log");
+        fs::write(&path, "old match\nskipped\n").unwrap();
+
+        let stop = AtomicBool::new(false);
+        let pause = || thread::sleep(Duration::from_millis(100));
+
+        let found = thread::scope(|s| {
+            s.spawn(|| {
+                pause();
+                let mut log = OpenOptions::new().append(true).open(&path).unwrap();
+                log.write_all(b"new match\n").unwrap();
+                pause();
+
+                // shorter than what was read, so it must have been truncated
+                fs::write(&path, "cut match\n").unwrap();
+                pause();
+
+                fs::rename(&path, dir.path().join("app.log.1")).unwrap();
+                fs::write(&path, "rotated match\n").unwrap();
+                pause();
+
+                stop.store(true, Ordering::Relaxed);
+            });
+
+            let reader = FollowReader::open(&path, &stop, Duration::from_millis(10)).unwrap();
+            let mut found = Vec::new();
+
+            search_reader(
+                BufReader::new(reader),
+                |line| find_case_sensitive("match", line),
+                SearchOptions::default(),
+                |line| {
+                    if let SearchLine::Match(m) = line {
+                        found.push(m.line.to_string());
+                    }
+                    Ok(())
+                },
+            )
+            .unwrap();
+
+            found
+        });
+
+        assert_eq!(
+            vec!["old match", "new match", "cut match", "rotated match"],
+            found
+        );
+    }
+}
@@ -0,0 +1,201 @@
+use std::{collections::HashMap, ops::Range};
+
+/// Queries up to this many characters fit in one machine word for the bit-parallel search.
+const WORD: usize = u64::BITS as usize;
+
+/// `Fuzzy` struct and implementations
+///
+/// A query matched with up to `max_distance` insertions, deletions or substitutions, using
+/// Myers' bit-parallel algorithm. Longer queries fall back to the plain dynamic programming
+/// version, which finds the same matches more slowly.
+#[derive(Debug)]
+pub struct Fuzzy {
+    query: Vec<char>,
+    /// For each character of the query, a bit set at every position where it occurs.
+    peq: HashMap<char, u64>,
+    max_distance: usize,
+}
+
+impl Fuzzy {
+    pub fn new(query: &str, max_distance: usize) -> Fuzzy {
+        let query: Vec<char> = query.chars().collect();
+        let mut peq = HashMap::new();
+
+        for (i, &c) in query.iter().enumerate().take(WORD) {
+            *peq.entry(c).or_insert(0) |= 1 << i;
+        }
+
+        Fuzzy {
+            query,
+            peq,
+            max_distance,
+        }
+    }
+
+    /// Byte range and edit distance of the first match in `text`.
+    ///
+    /// Of the overlapping matches around the first place the query is close enough, the closest
+    /// one is reported.
+    pub fn find(&self, text: &str) -> Option<(Range<usize>, usize)> {
+        // a query that short matches anywhere by inserting all of it
+        if self.query.len() <= self.max_distance {
+            return Some((0..0, self.query.len()));
+        }
+
+        let chars: Vec<(usize, char)> = text.char_indices().collect();
+        let scores = if self.query.len() <= WORD {
+            self.scores_bit_parallel(&chars)
+        } else {
+            self.scores_dynamic(&chars)
+        };
+
+        // the first end within the distance, moved along while the distance keeps improving, or
+        // for a few characters, while it stays the same: `cafe` rather than `caf` for `café`
+        let first = scores
+            .iter()
+            .position(|&score| score <= self.max_distance)?;
+        let mut end = first;
+        while let Some(&next) = scores.get(end + 1) {
+            let improves = next < scores[end];
+            let same = next == scores[end] && end < first + self.max_distance;
+
+            if !improves && !same {
+                break;
+            }
+            end += 1;
+        }
+
+        let distance = scores[end];
+        let start = end + 1 - self.match_len(&chars[..=end], distance);
+
+        let byte_end = chars.get(end + 1).map_or(text.len(), |&(i, _)| i);
+        let byte_start = chars.get(start).map_or(byte_end, |&(i, _)| i);
+
+        Some((byte_start..byte_end, distance))
+    }
+
+    /// For every position in `chars`, the smallest edit distance of a match ending there.
+    fn scores_bit_parallel(&self, chars: &[(usize, char)]) -> Vec<usize> {
+        let last = 1 << (self.query.len() - 1);
+        // vertical deltas between adjacent cells of the current column: +1 and -1
+        let mut pv = !0u64;
+        let mut mv = 0u64;
+        let mut score = self.query.len();
+
+        chars
+            .iter()
+            .map(|(_, c)| {
+                let eq = self.peq.get(c).copied().unwrap_or(0);
+                let xv = eq | mv;
+                let xh = ((eq & pv).wrapping_add(pv) ^ pv) | eq;
+                let mut ph = mv | !(xh | pv);
+                let mut mh = pv & xh;
+
+                if ph & last != 0 {
+                    score += 1;
+                } else if mh & last != 0 {
+                    score -= 1;
+                }
+
+                // nothing is shifted into the first row, so a match can start anywhere
+                ph <<= 1;
+                mh <<= 1;
+                pv = mh | !(xv | ph);
+                mv = ph & xv;
+
+                score
+            })
+            .collect()
+    }
+
+    /// The same as `scores_bit_parallel`, one cell at a time.
+    fn scores_dynamic(&self, chars: &[(usize, char)]) -> Vec<usize> {
+        let mut column: Vec<usize> = (0..=self.query.len()).collect();
+
+        chars
+            .iter()
+            .map(|&(_, c)| {
+                let mut diagonal = column[0];
+
+                for (i, &q) in self.query.iter().enumerate() {
+                    let substitution = diagonal + usize::from(q != c);
+                    diagonal = column[i + 1];
+                    column[i + 1] = substitution.min(column[i + 1] + 1).min(column[i] + 1);
+                }
+
+                *column.last().unwrap()
+            })
+            .collect()
+    }
+
+    /// How many characters at the end of `text` the closest match ending there covers.
+    fn match_len(&self, text: &[(usize, char)], distance: usize) -> usize {
+        let longest = text.len().min(self.query.len() + self.max_distance);
+
+        // edit distances between the reversed query and the last `len` characters of `text`
+        let mut row: Vec<usize> = (0..=longest).collect();
+
+        for (i, &q) in self.query.iter().rev().enumerate() {
+            let mut next = vec![i + 1; longest + 1];
+
+            for len in 1..=longest {
+                let c = text[text.len() - len].1;
+                next[len] = (row[len - 1] + usize::from(q != c))
+                    .min(row[len] + 1)
+                    .min(next[len - 1] + 1);
+            }
+
+            row = next;
+        }
+
+        // of equally close matches, the longest covers the most of what was probably meant
+        row.iter()
+            .rposition(|&score| score == distance)
+            .unwrap_or(longest)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn insertions_deletions_and_substitutions() {
+        let fuzzy = Fuzzy::new("rust", 1);
+
+        assert_eq!(Some((3..7, 0)), fuzzy.find("I trust it"));
+        assert_eq!(Some((0..4, 1)), fuzzy.find("rist"));
+        assert_eq!(Some((0..3, 1)), fuzzy.find("rst"));
+        assert_eq!(Some((0..5, 1)), fuzzy.find("roust"));
+        assert_eq!(None, fuzzy.find("rat"));
+        // the first of several exact matches, not the last
+        assert_eq!(Some((0..4, 0)), Fuzzy::new("aaaa", 1).find("aaaaaaaa"));
+    }
+
+    #[test]
+    fn long_queries_match_the_same() {
+        let query = "the quick brown fox jumps over the lazy dog, again and again and again";
+        let text = "so thequick brown fox jumps ovr the lazy dog, again and again and agian!";
+
+        let fuzzy = Fuzzy::new(query, 4);
+        let chars: Vec<_> = text.char_indices().collect();
+
+        assert!(query.chars().count() > WORD);
+        assert_eq!(Some((3..71, 4)), fuzzy.find(text));
+
+        // the bit-parallel scores agree with the dynamic programming ones on a short query
+        let short = Fuzzy::new("lazy dgo", 2);
+        assert_eq!(
+            short.scores_dynamic(&chars),
+            short.scores_bit_parallel(&chars)
+        );
+    }
+
+    #[test]
+    fn distances_count_characters_not_bytes() {
+        let fuzzy = Fuzzy::new("café", 1);
+
+        assert_eq!(Some((0..5, 0)), fuzzy.find("café"));
+        assert_eq!(Some((4..8, 1)), fuzzy.find("the cafe"));
+    }
+}
@@ -0,0 +1,618 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs,
+    io::{self, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    str,
+    time::UNIX_EPOCH,
+};
+
+use tempfile::NamedTempFile;
+
+use crate::{
+    decode,
+    walk::{self, GlobFilter, SearchFile, WalkOptions},
+    Config, ConfigError, Mode,
+};
+
+/// Name of the index file, kept in the indexed directory. The walker skips it, even with
+/// `--hidden`, so it is never searched.
+pub const INDEX_FILE: &str = ".minigrep-index";
+
+const MAGIC: &[u8] = b"minigrep-index 1\n";
+
+/// `Fingerprint` struct and implementations
+///
+/// What an indexed file looked like when it was read; a file that no longer matches is stale.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fingerprint {
+    /// Modification time as seconds and nanoseconds since the Unix epoch.
+    pub modified: (u64, u32),
+    pub size: u64,
+}
+
+impl Fingerprint {
+    pub fn of(path: &Path) -> io::Result<Fingerprint> {
+        let metadata = fs::metadata(path)?;
+        let modified = metadata
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+
+        Ok(Fingerprint {
+            modified: (modified.as_secs(), modified.subsec_nanos()),
+            size: metadata.len(),
+        })
+    }
+}
+
+struct IndexedFile {
+    fingerprint: Fingerprint,
+    /// Every trigram in the file, sorted.
+    trigrams: Vec<u32>,
+}
+
+/// How many files an update read again, left alone and dropped.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Changes {
+    pub reindexed: usize,
+    pub unchanged: usize,
+    pub removed: usize,
+}
+
+impl Changes {
+    pub fn is_empty(&self) -> bool {
+        self.reindexed == 0 && self.removed == 0
+    }
+}
+
+/// `Index` struct and implementations
+///
+/// The trigrams of every file under a directory, keyed by path relative to it. A line can only
+/// contain a literal if its file contains every trigram of the literal, so files missing one of
+/// them needn't be searched at all.
+///
+/// Trigrams are taken from the bytes with ASCII letters lowercased. Case-insensitive searches
+/// still aren't narrowed, since Unicode case folding can match bytes the pattern doesn't have.
+pub struct Index {
+    root: PathBuf,
+    files: BTreeMap<PathBuf, IndexedFile>,
+}
+
+impl Index {
+    pub fn new(root: &Path) -> Index {
+        Index {
+            root: root.to_path_buf(),
+            files: BTreeMap::new(),
+        }
+    }
+
+    /// Load the index of `root`, or `None` if it has never been built.
+    pub fn load(root: &Path) -> io::Result<Option<Index>> {
+        let contents = match fs::read(root.join(INDEX_FILE)) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        // every length is checked against what's left of the file before anything is allocated
+        let mut rest = contents.as_slice();
+
+        if take(&mut rest, MAGIC.len() as u64).ok() != Some(MAGIC) {
+            return Err(invalid("not a minigrep index"));
+        }
+
+        let mut index = Index::new(root);
+
+        for _ in 0..read_u64(&mut rest)? {
+            let len = read_u64(&mut rest)?;
+            let path = str::from_utf8(take(&mut rest, len)?)
+                .map_err(|_| invalid("path is not UTF-8"))?
+                .to_string();
+
+            let fingerprint = Fingerprint {
+                modified: (read_u64(&mut rest)?, read_u64(&mut rest)? as u32),
+                size: read_u64(&mut rest)?,
+            };
+
+            let count = read_u64(&mut rest)?;
+            let bytes = take(&mut rest, count.saturating_mul(4))?;
+            let trigrams = bytes
+                .chunks_exact(4)
+                .map(|chunk| u32::from_le_bytes(chunk.try_into().unwrap()))
+                .collect();
+
+            index.files.insert(
+                PathBuf::from(path),
+                IndexedFile {
+                    fingerprint,
+                    trigrams,
+                },
+            );
+        }
+
+        Ok(Some(index))
+    }
+
+    /// Write the index next to the files it covers, replacing the old one atomically.
+    pub fn save(&self) -> io::Result<()> {
+        let temp = NamedTempFile::new_in(&self.root)?;
+        let mut out = BufWriter::new(temp);
+
+        out.write_all(MAGIC)?;
+        out.write_all(&(self.files.len() as u64).to_le_bytes())?;
+
+        for (path, file) in &self.files {
+            let path = path.to_string_lossy();
+            out.write_all(&(path.len() as u64).to_le_bytes())?;
+            out.write_all(path.as_bytes())?;
+            out.write_all(&file.fingerprint.modified.0.to_le_bytes())?;
+            out.write_all(&u64::from(file.fingerprint.modified.1).to_le_bytes())?;
+            out.write_all(&file.fingerprint.size.to_le_bytes())?;
+            out.write_all(&(file.trigrams.len() as u64).to_le_bytes())?;
+
+            for trigram in &file.trigrams {
+                out.write_all(&trigram.to_le_bytes())?;
+            }
+        }
+
+        let temp = out.into_inner().map_err(|e| e.into_error())?;
+        // temporary files are only readable by their owner, but anyone searching the directory
+        // should be able to use its index
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+
+            temp.as_file()
+                .set_permissions(fs::Permissions::from_mode(0o644))?;
+        }
+        temp.persist(self.root.join(INDEX_FILE))?;
+
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// Read again every file in `files` under the root that is new or changed since it was
+    /// indexed. With `prune`, files no longer in `files` are dropped from the index.
+    ///
+    /// A file that can't be read, say because it was deleted since the walk, is dropped from the
+    /// index too, so it is searched and the search reports what's wrong with it.
+    pub fn update(&mut self, files: &[SearchFile], prune: bool) -> io::Result<Changes> {
+        let mut changes = Changes::default();
+        let mut seen = HashSet::new();
+
+        for file in files {
+            let Ok(relative) = file.path.strip_prefix(&self.root) else {
+                continue;
+            };
+            seen.insert(relative.to_path_buf());
+
+            let Ok(fingerprint) = Fingerprint::of(&file.path) else {
+                changes.removed += usize::from(self.files.remove(relative).is_some());
+                continue;
+            };
+            if self
+                .files
+                .get(relative)
+                .is_some_and(|indexed| indexed.fingerprint == fingerprint)
+            {
+                changes.unchanged += 1;
+                continue;
+            }
+
+            // UTF-16 is indexed as the UTF-8 it is searched as
+            let Ok(trigrams) = fs::File::open(&file.path).and_then(|f| trigrams(decode(f, None)))
+            else {
+                changes.removed += usize::from(self.files.remove(relative).is_some());
+                continue;
+            };
+            self.files.insert(
+                relative.to_path_buf(),
+                IndexedFile {
+                    fingerprint,
+                    trigrams,
+                },
+            );
+            changes.reindexed += 1;
+        }
+
+        if prune {
+            let before = self.files.len();
+            self.files.retain(|path, _| seen.contains(path));
+            changes.removed += before - self.files.len();
+        }
+
+        Ok(changes)
+    }
+
+    /// Can the file at `path` contain a line matching one of the literals behind `needles`?
+    ///
+    /// Files the index doesn't know about might, so they have to be searched.
+    pub fn may_match(&self, path: &Path, needles: &[Vec<u32>]) -> bool {
+        let Some(file) = path
+            .strip_prefix(&self.root)
+            .ok()
+            .and_then(|relative| self.files.get(relative))
+        else {
+            return true;
+        };
+
+        needles.iter().any(|needle| {
+            needle
+                .iter()
+                .all(|trigram| file.trigrams.binary_search(trigram).is_ok())
+        })
+    }
+}
+
+/// The next `len` bytes of `rest`, or an error if the index ends first.
+fn take<'a>(rest: &mut &'a [u8], len: u64) -> io::Result<&'a [u8]> {
+    let len = usize::try_from(len)
+        .ok()
+        .filter(|&len| len <= rest.len())
+        .ok_or_else(|| invalid("index is truncated"))?;
+
+    let (taken, after) = rest.split_at(len);
+    *rest = after;
+    Ok(taken)
+}
+
+fn read_u64(rest: &mut &[u8]) -> io::Result<u64> {
+    let bytes = take(rest, 8)?;
+    Ok(u64::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// Every distinct trigram in `reader`, sorted, with ASCII letters lowercased first.
+pub fn trigrams(mut reader: impl Read) -> io::Result<Vec<u32>> {
+    let mut seen = HashSet::new();
+    let mut window = 0u32;
+    let mut len = 0;
+    let mut buf = vec![0; 64 * 1024];
+
+    // the window carries over between reads, so trigrams spanning two reads are kept too
+    loop {
+        let read = reader.read(&mut buf)?;
+        if read == 0 {
+            break;
+        }
+
+        for &byte in &buf[..read] {
+            window = (window << 8 | u32::from(byte.to_ascii_lowercase())) & 0xff_ffff;
+            len += 1;
+            if len >= 3 {
+                seen.insert(window);
+            }
+        }
+    }
+
+    let mut trigrams: Vec<u32> = seen.into_iter().collect();
+    trigrams.sort_unstable();
+    Ok(trigrams)
+}
+
+/// The trigrams each of the search's patterns needs, or `None` when the index can't narrow the
+/// search.
+///
+/// Only case-sensitive literal patterns of UTF-8 input can be narrowed. Inverted searches,
+/// `-c`/`-L` and `--follow` have to look at every file. Unicode case folding and normalization
+/// can match bytes the pattern doesn't contain, even for an ASCII pattern: `-i office` matches
+/// `oﬃce`.
+pub fn needles(config: &Config, patterns: &[String]) -> Option<Vec<Vec<u32>>> {
+    let literal = !config.regex && config.fuzzy.is_none() && config.normalization.is_none();
+    // a file can be selected by what it lacks, or by only some of the terms
+    let expression = config.query_expr.is_some();
+    // the index holds trigrams of UTF-8, not of other encodings
+    let utf8 = config.encoding.is_none();
+    // a file followed for new lines can match later even though it doesn't now
+    let every_file = config.invert
+        || config.follow
+        || matches!(config.mode, Mode::Count | Mode::FilesWithoutMatch);
+
+    if !literal || !utf8 || every_file || expression || config.ignore_case || patterns.is_empty() {
+        return None;
+    }
+
+    patterns
+        .iter()
+        .map(|pattern| trigrams(pattern.as_bytes()).ok())
+        .collect()
+}
+
+/// Drop the files that the index of the directory they were found in rules out.
+///
+/// Each index is brought up to date with the files first, so stale and new files are read again
+/// and the updated index is saved. Files outside an indexed directory, or under one whose index
+/// can't be loaded, are kept.
+pub fn narrow(
+    paths: &[String],
+    files: Vec<SearchFile>,
+    needles: &[Vec<u32>],
+) -> io::Result<Vec<SearchFile>> {
+    let mut indexes = Vec::new();
+
+    for path in paths {
+        let root = Path::new(path);
+        if !root.is_dir() {
+            continue;
+        }
+
+        // the index only saves work, so the files of a directory whose index is broken are all
+        // searched
+        let mut index = match Index::load(root) {
+            Ok(Some(index)) => index,
+            Ok(None) => continue,
+            Err(e) => {
+                eprintln!("minigrep: {}: {e}", root.join(INDEX_FILE).display());
+                continue;
+            }
+        };
+
+        if !index.update(&files, false)?.is_empty() {
+            // a directory we can't write to still gets searched, just without saving the update
+            if let Err(e) = index.save() {
+                eprintln!("minigrep: {}: {e}", root.join(INDEX_FILE).display());
+            }
+        }
+
+        indexes.push(index);
+    }
+
+    Ok(files
+        .into_iter()
+        .filter(|file| {
+            file.explicit
+                || indexes
+                    .iter()
+                    .all(|index| index.may_match(&file.path, needles))
+        })
+        .collect())
+}
+
+/// Run `minigrep index ARGS...`; only `build <DIR>...` exists.
+///
+/// Building is incremental: only files that are new or changed since the last build are read.
+pub fn command(
+    mut args: impl Iterator<Item = String>,
+) -> Result<String, Box<dyn std::error::Error>> {
+    if args.next().as_deref() != Some("build") {
+        return Err(ConfigError::IndexUsage.into());
+    }
+
+    let dirs: Vec<String> = args.collect();
+    if dirs.is_empty() {
+        return Err(ConfigError::IndexUsage.into());
+    }
+
+    let mut report = String::new();
+
+    for dir in &dirs {
+        let root = Path::new(dir);
+        if !root.is_dir() {
+            return Err(format!("{dir}: not a directory").into());
+        }
+
+        let filter = GlobFilter::new(&[])?;
+        // directories that can't be read are reported, and their files are left out of the index
+        let (files, _) =
+            walk::collect_files(std::slice::from_ref(dir), &filter, WalkOptions::default())?;
+
+        let mut index = Index::load(root)?.unwrap_or_else(|| Index::new(root));
+        let changes = index.update(&files, true)?;
+        index.save()?;
+
+        report.push_str(&format!(
+            "{dir}: {} files indexed ({} read, {} unchanged, {} removed)\n",
+            index.len(),
+            changes.reindexed,
+            changes.unchanged,
+            changes.removed
+        ));
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn needle(text: &str) -> Vec<Vec<u32>> {
+        vec![trigrams(text.as_bytes()).unwrap()]
+    }
+
+    fn walk_files(root: &str) -> Vec<SearchFile> {
+        let filter = GlobFilter::new(&[]).unwrap();
+        let (files, _) =
+            walk::collect_files(&[root.to_string()], &filter, WalkOptions::default()).unwrap();
+        files
+    }
+
+    #[test]
+    fn trigrams_are_case_folded() {
+        let abc = 0x616263;
+
+        assert_eq!(vec![abc, 0x626364], trigrams("ABcd".as_bytes()).unwrap());
+        assert_eq!(3, trigrams("abcabc".as_bytes()).unwrap().len());
+        assert!(trigrams("ab".as_bytes()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn narrows_and_reindexes_stale_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().to_str().unwrap().to_string();
+        fs::write(dir.path().join("poem.txt"), "I'm nobody! Who are you?").unwrap();
+        fs::write(dir.path().join("rust.txt"), "safe, fast, productive").unwrap();
+
+        command(["build".to_string(), root.clone()].into_iter()).unwrap();
+        let index = Index::load(dir.path()).unwrap().unwrap();
+
+        assert_eq!(2, index.len());
+        assert!(index.may_match(&dir.path().join("poem.txt"), &needle("NOBODY")));
+        assert!(!index.may_match(&dir.path().join("rust.txt"), &needle("nobody")));
+        // too short to have a trigram, so nothing can be ruled out
+        assert!(index.may_match(&dir.path().join("rust.txt"), &needle("no")));
+
+        // a different size makes the file stale, whatever its modification time
+        fs::write(dir.path().join("rust.txt"), "nobody writes C++ anymore").unwrap();
+        let files = walk_files(&root);
+        let narrowed = narrow(&[root], files, &needle("nobody")).unwrap();
+
+        assert_eq!(2, narrowed.len());
+        let index = Index::load(dir.path()).unwrap().unwrap();
+        assert!(index.may_match(&dir.path().join("rust.txt"), &needle("nobody")));
+    }
+
+    #[test]
+    fn unreadable_files_are_stale() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().to_str().unwrap().to_string();
+        fs::write(dir.path().join("a.txt"), "alpha").unwrap();
+        fs::write(dir.path().join("b.txt"), "beta").unwrap();
+
+        let mut files = walk_files(&root);
+        let mut index = Index::new(dir.path());
+        index.update(&files, true).unwrap();
+        index.save().unwrap();
+
+        // deleted between the walk and the update
+        fs::remove_file(dir.path().join("b.txt")).unwrap();
+        let missing = SearchFile {
+            path: dir.path().join("c.txt"),
+            explicit: false,
+        };
+        files.push(missing);
+        let changes = index.update(&files, false).unwrap();
+
+        assert_eq!(
+            Changes {
+                reindexed: 0,
+                unchanged: 1,
+                removed: 1,
+            },
+            changes
+        );
+        assert_eq!(1, index.len());
+        assert!(index.may_match(&dir.path().join("b.txt"), &needle("alpha")));
+        // and so both get searched, rather than the search failing
+        assert_eq!(3, narrow(&[root], files, &needle("alpha")).unwrap().len());
+    }
+
+    #[test]
+    fn corrupt_indexes_are_skipped() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("a.txt"), "alpha").unwrap();
+        let mut index = Index::new(dir.path());
+        let root = dir.path().to_str().unwrap().to_string();
+        let files = walk_files(&root);
+        index.update(&files, true).unwrap();
+        index.save().unwrap();
+        let saved = fs::read(dir.path().join(INDEX_FILE)).unwrap();
+
+        let huge = u64::MAX / 16;
+        let mut huge_path = MAGIC.to_vec();
+        huge_path.extend(1u64.to_le_bytes());
+        huge_path.extend(huge.to_le_bytes());
+        let mut huge_count = saved.clone();
+        // the trigram count comes right before the trigrams, at the end of the only file
+        let at = saved.len() - 4 * index.files.values().next().unwrap().trigrams.len() - 8;
+        huge_count[at..at + 8].copy_from_slice(&huge.to_le_bytes());
+
+        for contents in [
+            b"garbage".to_vec(),
+            saved[..saved.len() - 1].to_vec(),
+            huge_path,
+            huge_count,
+        ] {
+            fs::write(dir.path().join(INDEX_FILE), contents).unwrap();
+            let error = Index::load(dir.path()).err().unwrap();
+            assert_eq!(io::ErrorKind::InvalidData, error.kind());
+
+            let files = walk_files(&root);
+            let narrowed = narrow(std::slice::from_ref(&root), files, &needle("zzz")).unwrap();
+            assert_eq!(1, narrowed.len());
+        }
+    }
+
+    #[test]
+    fn ignore_case_is_never_narrowed() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().to_str().unwrap().to_string();
+        fs::write(dir.path().join("lig.txt"), "the of\u{fb01}ce\n").unwrap();
+        fs::write(dir.path().join("kelvin.txt"), "0 \u{212a}elvin\n").unwrap();
+        command(["build".to_string(), root].into_iter()).unwrap();
+        let index = Index::load(dir.path()).unwrap().unwrap();
+
+        let config = Config {
+            ignore_case: true,
+            ..Config::default()
+        };
+
+        for (file, pattern) in [("lig.txt", "office"), ("kelvin.txt", "kelvin")] {
+            let patterns = vec![pattern.to_string()];
+            let contents = fs::read_to_string(dir.path().join(file)).unwrap();
+
+            // Unicode case folding matches bytes the trigrams of the pattern don't have
+            let matcher = crate::PatternMatcher::new(&config, &patterns).unwrap();
+            assert!(
+                crate::Matcher::find(&matcher, &contents).is_some(),
+                "{file}"
+            );
+            assert!(!index.may_match(&dir.path().join(file), &needle(pattern)));
+
+            assert_eq!(None, needles(&config, &patterns));
+        }
+
+        let config = Config::default();
+        assert!(needles(&config, &["office".to_string()]).is_some());
+    }
+
+    #[test]
+    fn followed_files_are_never_narrowed() {
+        let patterns = vec!["ERROR".to_string()];
+        let config = Config {
+            follow: true,
+            ..Config::default()
+        };
+
+        assert_eq!(None, needles(&config, &patterns));
+        assert!(needles(&Config::default(), &patterns).is_some());
+    }
+
+    #[test]
+    fn rebuilding_is_incremental() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().to_str().unwrap().to_string();
+        let build = || command(["build".to_string(), root.clone()].into_iter()).unwrap();
+        fs::write(dir.path().join("a.txt"), "alpha").unwrap();
+        fs::write(dir.path().join("b.txt"), "beta").unwrap();
+
+        assert!(build().ends_with("2 files indexed (2 read, 0 unchanged, 0 removed)\n"));
+
+        // readable by everyone who can search the directory
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+
+            let metadata = fs::metadata(dir.path().join(INDEX_FILE)).unwrap();
+            assert_eq!(0o644, metadata.permissions().mode() & 0o777);
+        }
+
+        fs::remove_file(dir.path().join("b.txt")).unwrap();
+        assert!(build().ends_with("1 files indexed (0 read, 1 unchanged, 1 removed)\n"));
+
+        assert!(matches!(
+            command(["rebuild".to_string()].into_iter()),
+            Err(e) if e.to_string().starts_with("Usage: minigrep index build")
+        ));
+    }
+}
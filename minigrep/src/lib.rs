@@ -1,55 +1,1037 @@
-use std::{fs, env};
+use std::collections::{BTreeMap, VecDeque};
 use std::error::Error;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::ops::Range;
+use std::path::Path;
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Instant;
+use std::{env, fs};
 
+use encoding_rs::Encoding;
+use encoding_rs_io::DecodeReaderBytesBuilder;
+use regex::{Regex, RegexBuilder};
+
+pub mod args;
+pub mod follow;
+pub mod fuzzy;
+pub mod index;
+pub mod matcher;
+pub mod pool;
+pub mod printer;
+pub mod query;
+pub mod replace;
+pub mod search;
+pub mod unicode;
+pub mod walk;
+
+pub use args::ConfigError;
+use follow::FollowReader;
+pub use matcher::{CaseInsensitive, Literal, Matcher, PatternMatcher};
+use pool::ThreadPool;
+use printer::{ColorChoice, JsonPrinter, OutputFormat, Printer, StandardPrinter, Stats};
+use query::Expr;
+use replace::Rewrite;
+pub use search::{Searcher, Sink};
+use unicode::Normalization;
+use walk::{GlobFilter, SearchFile, WalkOptions};
+
+#[derive(Debug, Default)]
 pub struct Config {
     pub query: String,
-    pub file_path: String,
+    /// Patterns given with `-e`; when any are given, together with `pattern_files`, they replace
+    /// `query`.
+    pub patterns: Vec<String>,
+    /// Files given with `-f`, holding one pattern per line.
+    pub pattern_files: Vec<String>,
+    /// A boolean expression of patterns given with `--query-expr`; it replaces `query`.
+    pub query_expr: Option<Expr>,
+    /// Files and directories to search; directories are walked recursively and `-` is stdin.
+    pub paths: Vec<String>,
+    /// Globs applied to walked files, e.g. `*.rs` or `!target/**`.
+    pub globs: Vec<String>,
+    /// Search walked files even when `.gitignore`, `.ignore` or `.git/info/exclude` exclude them.
+    pub no_ignore: bool,
+    /// Walk into hidden files and directories.
+    pub hidden: bool,
+    /// Don't use the trigram index of a searched directory to skip files.
+    pub no_index: bool,
+    /// Match with Unicode case folding, so `ß` matches `SS`.
     pub ignore_case: bool,
+    /// Normalize the query and every line before matching.
+    pub normalization: Option<Normalization>,
+    /// Treat `query` as a regular expression instead of a literal substring.
+    pub regex: bool,
+    /// Let matches span lines; `^` and `$` in a regex still match at every line.
+    pub multiline: bool,
+    /// Match `query` with up to this many inserted, deleted or substituted characters.
+    pub fuzzy: Option<usize>,
+    /// Print fuzzy matches closest first, once every file has been searched.
+    pub sort_distance: bool,
+    /// Output template for regex mode; `$1` and `${name}` expand to capture groups.
+    pub format: Option<String>,
+    /// Print matching lines with every match replaced by this template; in regex mode `$1` and
+    /// `${name}` expand to capture groups.
+    pub replace: Option<String>,
+    /// Write the replacements back to the searched files instead of printing them.
+    pub in_place: bool,
+    /// Keep a copy of each file rewritten in place, named with this suffix appended.
+    pub backup_suffix: Option<String>,
+    /// Print a diff of what an in-place rewrite would change instead of rewriting.
+    pub dry_run: bool,
+    /// After searching each file, keep searching what is appended to it, like `tail -f`.
+    pub follow: bool,
+    /// Search this many files at a time, each on a thread of its own.
+    pub threads: Option<usize>,
+    /// Search and print files in the order of their paths, even with `threads`.
+    pub sort_files: bool,
+    /// Prefix each line with its 1-based line number.
+    pub line_number: bool,
+    /// Prefix each line with the byte offset of its first byte in the input.
+    pub byte_offset: bool,
+    pub context: Context,
+    pub output: OutputFormat,
+    pub color: ColorChoice,
+    /// Select the lines that don't match instead of the ones that do.
+    pub invert: bool,
+    /// Stop searching a file after this many selected lines.
+    pub max_count: Option<usize>,
+    pub mode: Mode,
+    pub binary: BinaryFiles,
+    /// Decode input from this encoding instead of UTF-8; a UTF-16 byte order mark overrides it.
+    pub encoding: Option<&'static Encoding>,
+}
+
+/// What gets reported about the selected lines.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Mode {
+    /// Print every selected line.
+    #[default]
+    Lines,
+    /// Print the number of selected lines per file, like `grep -c`.
+    Count,
+    /// Print the path of each file with a selected line, like `grep -l`.
+    FilesWithMatches,
+    /// Print the path of each file without a selected line, like `grep -L`.
+    FilesWithoutMatch,
+}
+
+/// What happens to binary input, recognized by a NUL byte.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BinaryFiles {
+    /// Report the first selected line as "binary file matches" instead of printing it.
+    #[default]
+    Report,
+    /// Stop searching the input, like `grep -I`.
+    Skip,
+    /// Search it like any other input, like `grep -a`.
+    Text,
+}
+
+/// How `search_reader` selects lines and what it sends around them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SearchOptions {
+    pub context: Context,
+    /// Select the lines `find` rejects.
+    pub invert: bool,
+    /// Stop after this many selected lines, once their trailing context has been sent.
+    pub max_count: Option<usize>,
+    pub binary: BinaryFiles,
+}
+
+/// What a `run` found; `main` turns it into grep's exit status.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// At least one line (or, for `-L`, one file) was selected.
+    Selected,
+    NothingSelected,
+    /// Some input couldn't be searched.
+    Errors,
+}
+
+impl Outcome {
+    /// 0 when something was selected, 1 when nothing was, 2 on errors.
+    pub fn exit_code(self) -> i32 {
+        match self {
+            Outcome::Selected => 0,
+            Outcome::NothingSelected => 1,
+            Outcome::Errors => 2,
+        }
+    }
+}
+
+/// Lines of context to print around each match, like grep's `-B` and `-A`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Context {
+    pub before: usize,
+    pub after: usize,
+}
+
+impl Context {
+    pub fn is_empty(&self) -> bool {
+        self.before == 0 && self.after == 0
+    }
+}
+
+/// A line selected by a search: one that matched the query, or with `invert`, one that didn't.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Match<'a> {
+    /// 1-based line number.
+    pub line_number: usize,
+    /// Offset of the line's first byte within the searched input.
+    pub byte_offset: usize,
+    /// Range of the first match within `line`; empty at 0 for an inverted match.
+    pub byte_range: Range<usize>,
+    pub line: &'a str,
+}
+
+/// The lines a match found with `--multiline` spans, merged with any other matches on them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Block<'a> {
+    /// 1-based number of the first line.
+    pub line_number: usize,
+    /// 1-based number of the last line.
+    pub end_line_number: usize,
+    /// Offset of the first line's first byte within the searched input.
+    pub byte_offset: usize,
+    /// Pattern number and range within `lines` of every match.
+    pub matches: Vec<(usize, Range<usize>)>,
+    /// The whole lines, separated by their line terminators but without the last one.
+    pub lines: &'a str,
+}
+
+/// A line handed out by `search_reader`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SearchLine<'a> {
+    Match(Match<'a>),
+    /// A non-matching line printed because it is near a match.
+    Context {
+        line_number: usize,
+        byte_offset: usize,
+        line: &'a str,
+    },
+    /// A gap between two groups of lines that aren't adjacent; grep prints it as `--`.
+    Break,
+    /// The first selected line of binary input, which is reported instead of printed. No more
+    /// lines are sent after it.
+    Binary {
+        line_number: usize,
+        byte_offset: usize,
+    },
+    /// Several lines selected together by a match that spans them; only a `Searcher` set to
+    /// `multiline` sends them.
+    Block(Block<'a>),
 }
 
 impl Config {
-    pub fn build(mut args: impl Iterator<Item = String>) -> Result<Config, &'static str> {
-        args.next();
-        
-        let query = match args.next() {
-            Some(arg) => arg,
-            _None => return Err("Didn't get a query string"),
-        };
+    /// Build a `Config` from command-line arguments, program name first.
+    ///
+    /// `-i`/`--ignore-case` and `-s`/`--case-sensitive` take precedence over the `IGNORE_CASE`
+    /// environment variable, which is only consulted when neither flag is given.
+    pub fn build(args: impl Iterator<Item = String>) -> Result<Config, ConfigError> {
+        args::parse(args, env::var("IGNORE_CASE").is_ok())
+    }
 
-        let file_path = match args.next() {
-            Some(arg) => arg,
-            _None => return Err("Didn't get a file path"),
-        };
+    /// Every pattern to search for: the terms of `query_expr`, the `-e` patterns followed by the
+    /// lines of each `-f` file, or just `query` when none of them was given.
+    pub fn load_patterns(&self) -> io::Result<Vec<String>> {
+        if let Some(expr) = &self.query_expr {
+            return Ok(expr.terms().into_iter().cloned().collect());
+        }
 
-        let ignore_case = env::var("IGNORE_CASE").is_ok();
+        if self.patterns.is_empty() && self.pattern_files.is_empty() {
+            return Ok(vec![self.query.clone()]);
+        }
 
-        Ok(Config {
-            query,
-            file_path,
-            ignore_case,
-        })
+        let mut patterns = self.patterns.clone();
+
+        for file in &self.pattern_files {
+            let contents = fs::read_to_string(file)
+                .map_err(|e| io::Error::new(e.kind(), format!("{file}: {e}")))?;
+            patterns.extend(contents.lines().map(String::from));
+        }
+
+        Ok(patterns)
+    }
+
+    /// Compile `query` as a regular expression. Case-insensitivity is handled by the regex engine
+    /// itself, so lines are never lowercased.
+    pub fn build_regex(&self) -> Result<Regex, regex::Error> {
+        RegexBuilder::new(&self.query)
+            .case_insensitive(self.ignore_case)
+            .build()
     }
 }
 
-pub fn run(config: Config) -> Result<(), Box<dyn Error>> {
+pub fn run(config: Config) -> Result<Outcome, Box<dyn Error>> {
     // Box<dyn Error> is a type that implements the Error trait. `dyn` is short for dynamic
-    let contents = fs::read_to_string(config.file_path)?; // the error variant is returned to the calling code to handle
+    let filter = GlobFilter::new(&config.globs)?;
+    let walk_options = WalkOptions {
+        no_ignore: config.no_ignore,
+        hidden: config.hidden,
+    };
+    let (files, unreadable) = walk::collect_files(&config.paths, &filter, walk_options)?;
+    let outcome = search_files(config, files)?;
+
+    // like `grep -r`, a directory that couldn't be read is an error even when lines were selected
+    Ok(if unreadable > 0 {
+        Outcome::Errors
+    } else {
+        outcome
+    })
+}
+
+/// Search the files found for `config`, the way it asks to.
+fn search_files(config: Config, files: Vec<SearchFile>) -> Result<Outcome, Box<dyn Error>> {
+    let patterns = config.load_patterns()?;
+    let matcher = PatternMatcher::new(&config, &patterns)?;
+
+    let files = match index::needles(&config, &patterns) {
+        Some(needles) if !config.no_index => index::narrow(&config.paths, files, &needles)?,
+        _ => files,
+    };
+
+    if config.in_place || config.dry_run {
+        return rewrite_files(&config, &matcher, &files);
+    }
+
+    // like `grep -r`, prefix lines with their file once more than one file can match
+    let with_path =
+        config.paths.len() > 1 || config.paths.iter().any(|path| Path::new(path).is_dir());
+
+    if config.follow {
+        return follow_files(&config, &matcher, &files, with_path);
+    }
 
-    let results = if config.ignore_case {
-        search_case_insensitive(&config.query, &contents)
+    let mut options = SearchOptions {
+        context: config.context,
+        invert: config.invert,
+        max_count: config.max_count,
+        binary: config.binary,
+    };
+
+    // sorted lines are printed apart from the lines around them
+    let sorting = config.sort_distance && config.mode == Mode::Lines;
+    let mut ranked = Vec::new();
+
+    if config.mode != Mode::Lines || sorting {
+        options.context = Context::default();
+    }
+
+    if config.mode != Mode::Lines {
+        // nothing gets printed from the lines, so binary input can be counted like text
+        if options.binary == BinaryFiles::Report {
+            options.binary = BinaryFiles::Text;
+        }
+    }
+
+    // one selected line is enough to decide whether a file is listed
+    if let Mode::FilesWithMatches | Mode::FilesWithoutMatch = config.mode {
+        options.max_count = Some(1);
+    }
+
+    let mut files = files;
+    if config.sort_files {
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+    }
+
+    if let Some(threads) = config.threads.filter(|&threads| threads > 1) {
+        return search_parallel(config, matcher, files, options, with_path, threads);
+    }
+
+    let color = config.color.enabled();
+    let mut printer = new_printer(io::stdout().lock(), &config, &matcher, with_path, color);
+
+    let started = Instant::now();
+    let mut stats = Stats::default();
+    let mut errors = 0;
+
+    for file in &files {
+        let ranked = sorting.then_some(&mut ranked);
+        let (file_stats, file_errors) =
+            search_file(&config, &matcher, file, options, printer.as_mut(), ranked)?;
+
+        stats.add(&file_stats);
+        errors += file_errors;
+    }
+
+    // a stable sort keeps equally close lines in the order they were found
+    ranked.sort_by_key(|ranked| ranked.distance);
+    for ranked in &ranked {
+        printer.line(&ranked.path, &SearchLine::Match(ranked.as_match()))?;
+    }
+
+    printer.summary(&stats, started.elapsed())?;
+
+    Ok(outcome(&config, &stats, errors))
+}
+
+/// The printer `config.output` asks for, writing to `out`.
+fn new_printer<'a, W: Write + 'a>(
+    out: W,
+    config: &'a Config,
+    matcher: &'a PatternMatcher,
+    with_path: bool,
+    color: bool,
+) -> Box<dyn Printer + 'a> {
+    match config.output {
+        OutputFormat::Standard => {
+            Box::new(StandardPrinter::new(out, config, matcher, with_path, color))
+        }
+        OutputFormat::Json => Box::new(JsonPrinter::new(out, matcher)),
+    }
+}
+
+/// Search one file, printing what it selects, or with `ranked`, holding its lines back to be
+/// sorted.
+///
+/// Returns the file's stats and the number of errors reading it, which are reported here; like
+/// grep, one unreadable file doesn't abort the rest of the search. Only failing to print is an
+/// error.
+fn search_file(
+    config: &Config,
+    matcher: &PatternMatcher,
+    file: &SearchFile,
+    options: SearchOptions,
+    printer: &mut dyn Printer,
+    mut ranked: Option<&mut Vec<Ranked>>,
+) -> io::Result<(Stats, usize)> {
+    let reader: Box<dyn BufRead> = if file.path.as_os_str() == "-" {
+        Box::new(BufReader::new(decode(io::stdin().lock(), config.encoding)))
+    } else {
+        match fs::File::open(&file.path) {
+            Ok(f) => Box::new(BufReader::new(decode(f, config.encoding))),
+            Err(e) => {
+                eprintln!("minigrep: {}: {e}", file.path.display());
+                return Ok((Stats::default(), 1));
+            }
+        }
+    };
+
+    let name = if file.path.as_os_str() == "-" {
+        String::from("(standard input)")
     } else {
-        search_case_sensitive(&config.query, &contents)
+        file.path.display().to_string()
+    };
+
+    let mut file_stats = Stats {
+        searches: 1,
+        ..Stats::default()
+    };
+    let mut errors = 0;
+    let mut write_failed = false;
+    let sorting = ranked.is_some();
+
+    if !sorting {
+        printer.begin(&name)?;
+    }
+
+    let mut sink = EveryLine(|line: SearchLine<'_>| {
+        match &line {
+            SearchLine::Match(_) | SearchLine::Binary { .. } => file_stats.matched_lines += 1,
+            SearchLine::Block(block) => {
+                file_stats.matched_lines += block.end_line_number - block.line_number + 1;
+            }
+            _ => {}
+        }
+
+        if config.mode != Mode::Lines {
+            return Ok(());
+        }
+
+        if let (Some(ranked), SearchLine::Match(m)) = (ranked.as_mut(), &line) {
+            ranked.push(Ranked::new(matcher, &name, m));
+            return Ok(());
+        }
+
+        printer
+            .line(&name, &line)
+            .inspect_err(|_| write_failed = true)
+    });
+
+    // results are printed as soon as they are found instead of after the whole input is read,
+    // unless matches can span lines
+    let searcher = Searcher {
+        options,
+        multiline: config.multiline,
     };
+    let searched = searcher.search_reader(matcher, reader, &mut sink);
+
+    match searched {
+        Ok(()) => {}
+        // failing to write the results is fatal, failing to read one input isn't
+        Err(e) if write_failed => return Err(e),
+        Err(e) => {
+            eprintln!("minigrep: {name}: {e}");
+            errors += 1;
+        }
+    }
+
+    if file_stats.matched_lines > 0 {
+        file_stats.searches_with_match = 1;
+    }
+
+    if !sorting {
+        printer.end(&name, &file_stats)?;
+    }
+
+    Ok((file_stats, errors))
+}
+
+/// Search `files` on a pool of `threads` workers.
+///
+/// Each worker prints a file to a buffer of its own, which is written out whole once the file has
+/// been searched, so the lines of different files never interleave. Files are written in the
+/// order they finish, or with `sort_files`, in the order of `files`.
+fn search_parallel(
+    config: Config,
+    matcher: PatternMatcher,
+    files: Vec<SearchFile>,
+    options: SearchOptions,
+    with_path: bool,
+    threads: usize,
+) -> Result<Outcome, Box<dyn Error>> {
+    let config = Arc::new(config);
+    let matcher = Arc::new(matcher);
+    // stdout is what decides, not the buffers the workers print to
+    let color = config.color.enabled();
+
+    let started = Instant::now();
+    let pool = ThreadPool::new(threads);
+
+    for (i, file) in files.into_iter().enumerate() {
+        let config = Arc::clone(&config);
+        let matcher = Arc::clone(&matcher);
+
+        pool.execute(move || {
+            let mut output = Vec::new();
+            let mut printer = new_printer(&mut output, &config, &matcher, with_path, color);
+            let searched = search_file(&config, &matcher, &file, options, printer.as_mut(), None);
+
+            drop(printer);
+            (i, searched, output)
+        });
+    }
+
+    let mut printer = new_printer(io::stdout().lock(), &config, &matcher, with_path, color);
+    let mut stats = Stats::default();
+    let mut errors = 0;
+
+    // finished files waiting for the ones before them, with `sort_files`
+    let mut waiting = BTreeMap::new();
+    let mut next = 0;
+
+    for (i, searched, output) in pool.into_results() {
+        // the workers print to memory, which doesn't fail
+        let (file_stats, file_errors) = searched?;
+        stats.add(&file_stats);
+        errors += file_errors;
+
+        if !config.sort_files {
+            printer.append(&output, &file_stats)?;
+            continue;
+        }
+
+        waiting.insert(i, (output, file_stats));
+        while let Some((output, file_stats)) = waiting.remove(&next) {
+            printer.append(&output, &file_stats)?;
+            next += 1;
+        }
+    }
+
+    printer.summary(&stats, started.elapsed())?;
+
+    Ok(outcome(&config, &stats, errors))
+}
+
+/// What searching to the end found, as `run` reports it.
+fn outcome(config: &Config, stats: &Stats, errors: usize) -> Outcome {
+    let selected = match config.mode {
+        Mode::FilesWithoutMatch => stats.searches_with_match < stats.searches,
+        _ => stats.matched_lines > 0,
+    };
+
+    if errors > 0 {
+        Outcome::Errors
+    } else if selected {
+        Outcome::Selected
+    } else {
+        Outcome::NothingSelected
+    }
+}
+
+/// `Ranked` struct and implementations
+///
+/// A selected line held back to be printed in order of edit distance.
+struct Ranked {
+    distance: usize,
+    path: String,
+    line_number: usize,
+    byte_offset: usize,
+    byte_range: Range<usize>,
+    line: String,
+}
+
+impl Ranked {
+    fn new(matcher: &PatternMatcher, path: &str, m: &Match<'_>) -> Ranked {
+        Ranked {
+            // an inverted match has no distance to speak of
+            distance: matcher
+                .find_distance(m.line)
+                .map_or(0, |(distance, _)| distance),
+            path: path.to_string(),
+            line_number: m.line_number,
+            byte_offset: m.byte_offset,
+            byte_range: m.byte_range.clone(),
+            line: m.line.to_string(),
+        }
+    }
+
+    fn as_match(&self) -> Match<'_> {
+        Match {
+            line_number: self.line_number,
+            byte_offset: self.byte_offset,
+            byte_range: self.byte_range.clone(),
+            line: &self.line,
+        }
+    }
+}
+
+/// Search each file like `run`, then keep reading what is appended to it, until every file has
+/// had `max_count` lines selected.
+///
+/// Every file is followed on its own thread. Lines are printed whole, but lines from different
+/// files are printed as they arrive, so they can interleave.
+fn follow_files(
+    config: &Config,
+    matcher: &PatternMatcher,
+    files: &[SearchFile],
+    with_path: bool,
+) -> Result<Outcome, Box<dyn Error>> {
+    let options = SearchOptions {
+        context: config.context,
+        invert: config.invert,
+        max_count: config.max_count,
+        binary: config.binary,
+    };
+
+    // the threads share one printer, so stdout is locked per line rather than held by one thread
+    let printer: Box<dyn Printer + Send> = match config.output {
+        OutputFormat::Standard => {
+            let color = config.color.enabled();
+            Box::new(StandardPrinter::new(
+                io::stdout(),
+                config,
+                matcher,
+                with_path,
+                color,
+            ))
+        }
+        OutputFormat::Json => Box::new(JsonPrinter::new(io::stdout(), matcher)),
+    };
+    let printer = Mutex::new(printer);
+
+    // nothing sets it: following only ends when minigrep is interrupted
+    let stop = AtomicBool::new(false);
+    let started = Instant::now();
+
+    let results: Vec<(String, io::Result<Stats>)> = thread::scope(|s| {
+        let threads: Vec<_> = files
+            .iter()
+            .map(|file| {
+                let name = file.path.display().to_string();
+                let searched = s.spawn(|| {
+                    let stdin = file.path.as_os_str() == "-";
+
+                    // standard input already waits for more until it is closed
+                    let reader: Box<dyn BufRead> = if stdin {
+                        Box::new(BufReader::new(decode(io::stdin().lock(), config.encoding)))
+                    } else {
+                        let follow = FollowReader::open(&file.path, &stop, follow::POLL_INTERVAL)?;
+                        Box::new(BufReader::new(decode(follow, config.encoding)))
+                    };
+
+                    let name = match stdin {
+                        true => String::from("(standard input)"),
+                        false => file.path.display().to_string(),
+                    };
+                    follow_file(reader, &name, matcher, options, &printer)
+                });
+                (name, searched)
+            })
+            .collect();
+
+        threads
+            .into_iter()
+            .map(|(name, searched)| (name, searched.join().expect("follow thread panicked")))
+            .collect()
+    });
+
+    let mut stats = Stats::default();
+    let mut errors = 0;
+
+    for (name, searched) in results {
+        match searched {
+            Ok(file_stats) => stats.add(&file_stats),
+            Err(e) => {
+                eprintln!("minigrep: {name}: {e}");
+                errors += 1;
+            }
+        }
+    }
+
+    let mut printer = printer.into_inner().expect("printer lock poisoned");
+    printer.summary(&stats, started.elapsed())?;
+
+    Ok(if errors > 0 {
+        Outcome::Errors
+    } else if stats.matched_lines > 0 {
+        Outcome::Selected
+    } else {
+        Outcome::NothingSelected
+    })
+}
+
+fn follow_file(
+    reader: impl BufRead,
+    name: &str,
+    matcher: &PatternMatcher,
+    options: SearchOptions,
+    printer: &Mutex<Box<dyn Printer + Send + '_>>,
+) -> io::Result<Stats> {
+    let mut stats = Stats {
+        searches: 1,
+        ..Stats::default()
+    };
+    let print = |f: &mut dyn FnMut(&mut dyn Printer) -> io::Result<()>| {
+        f(printer.lock().expect("printer lock poisoned").as_mut())
+    };
+
+    print(&mut |printer| printer.begin(name))?;
+
+    let mut sink = EveryLine(|line: SearchLine<'_>| {
+        if let SearchLine::Match(_) | SearchLine::Binary { .. } = line {
+            stats.matched_lines += 1;
+        }
+        print(&mut |printer| printer.line(name, &line))
+    });
+    Searcher::new(options).search_reader(matcher, reader, &mut sink)?;
+
+    if stats.matched_lines > 0 {
+        stats.searches_with_match = 1;
+    }
+    print(&mut |printer| printer.end(name, &stats))?;
+
+    Ok(stats)
+}
+
+/// A `Sink` handing everything a search sends to a closure, the way printers want it.
+struct EveryLine<F>(F);
+
+impl<F> Sink for EveryLine<F>
+where
+    F: FnMut(SearchLine<'_>) -> io::Result<()>,
+{
+    fn matched(&mut self, m: Match<'_>) -> io::Result<()> {
+        (self.0)(SearchLine::Match(m))
+    }
+
+    fn line(&mut self, line: SearchLine<'_>) -> io::Result<()> {
+        (self.0)(line)
+    }
+}
+
+/// Apply `config.replace` to each file in place, or with `dry_run`, print the diff instead.
+fn rewrite_files(
+    config: &Config,
+    matcher: &PatternMatcher,
+    files: &[SearchFile],
+) -> Result<Outcome, Box<dyn Error>> {
+    let template = config.replace.as_deref().unwrap_or_default();
+    let mut out = io::stdout().lock();
+    let mut changed = 0;
+    let mut errors = 0;
+
+    for file in files {
+        let name = file.path.display().to_string();
+
+        if file.path.as_os_str() == "-" {
+            eprintln!("minigrep: standard input can't be rewritten in place");
+            errors += 1;
+            continue;
+        }
+
+        let contents = match replace::read_text(&file.path) {
+            Ok(contents) => contents,
+            // binary and non-UTF-8 files found by walking are left alone quietly
+            Err(e) if e.kind() == io::ErrorKind::InvalidData && !file.explicit => continue,
+            Err(e) => {
+                eprintln!("minigrep: {name}: {e}");
+                errors += 1;
+                continue;
+            }
+        };
+
+        let rewrite = Rewrite::new(matcher, template, &contents);
+        if rewrite.changes.is_empty() {
+            continue;
+        }
+        changed += 1;
+
+        if config.dry_run {
+            rewrite.write_diff(&name, &mut out)?;
+        } else if let Err(e) = replace::write_atomically(
+            &file.path,
+            &rewrite.contents,
+            config.backup_suffix.as_deref(),
+        ) {
+            eprintln!("minigrep: {name}: {e}");
+            errors += 1;
+        }
+    }
+
+    Ok(if errors > 0 {
+        Outcome::Errors
+    } else if changed > 0 {
+        Outcome::Selected
+    } else {
+        Outcome::NothingSelected
+    })
+}
+
+/// Stream `reader` line by line, calling `on_line` for every selected line and for the
+/// surrounding context lines.
+///
+/// A line is selected when `find` matches it, or with `options.invert`, when it doesn't. Only the
+/// current line and up to `context.before` earlier lines are held in memory, so inputs of any
+/// size, and pipes, can be searched. Overlapping context windows are merged, and a
+/// `SearchLine::Break` is sent between groups that aren't adjacent.
+///
+/// Lines are read as bytes and invalid UTF-8 is replaced with U+FFFD, so `find` and `on_line` see
+/// text while byte offsets still count the bytes actually read. Input with a NUL byte in its
+/// first buffer, or on any line read, is binary and handled as `options.binary` says.
+pub(crate) fn search_reader<R, P, F>(
+    mut reader: R,
+    mut find: P,
+    options: SearchOptions,
+    mut on_line: F,
+) -> io::Result<()>
+where
+    R: BufRead,
+    P: FnMut(&str) -> Option<Range<usize>>,
+    F: FnMut(SearchLine<'_>) -> io::Result<()>,
+{
+    let context = options.context;
+
+    // the buffer is reused for every line, so its capacity only grows to the longest line
+    let mut buf = Vec::new();
+    let mut before: VecDeque<(usize, usize, String)> = VecDeque::with_capacity(context.before);
+    let mut after_remaining = 0;
+    let mut last_sent: Option<usize> = None;
+    let mut line_number = 0;
+    let mut byte_offset = 0;
+    let mut selected = 0;
+
+    let mut binary = match options.binary {
+        BinaryFiles::Text => false,
+        _ => reader.fill_buf()?.contains(&0),
+    };
+
+    // sends a `Break` first when the line doesn't directly follow the last one sent
+    let mut send = |line: SearchLine<'_>, line_number: usize, last_sent: &mut Option<usize>| {
+        if !context.is_empty() && last_sent.is_some_and(|last| line_number > last + 1) {
+            on_line(SearchLine::Break)?;
+        }
+        *last_sent = Some(line_number);
+        on_line(line)
+    };
+
+    loop {
+        // after the last selected line, only its trailing context is still wanted
+        let done = options.max_count.is_some_and(|max| selected >= max);
+        if done && after_remaining == 0 {
+            return Ok(());
+        }
+
+        buf.clear();
+
+        let read = reader.read_until(b'\n', &mut buf)?;
+        if read == 0 {
+            return Ok(());
+        }
+
+        line_number += 1;
+        let offset = byte_offset;
+        byte_offset += read;
+
+        if options.binary != BinaryFiles::Text && buf.contains(&0) {
+            binary = true;
+        }
+
+        if binary && options.binary == BinaryFiles::Skip {
+            return Ok(());
+        }
+
+        // strip the terminator the same way `str::lines` does
+        let bytes = match buf.strip_suffix(b"\n") {
+            Some(bytes) => bytes.strip_suffix(b"\r").unwrap_or(bytes),
+            None => &buf,
+        };
+        let line = String::from_utf8_lossy(bytes);
+        let line = line.as_ref();
+
+        let found = if done {
+            None
+        } else if options.invert {
+            match find(line) {
+                Some(_) => None,
+                None => Some(0..0),
+            }
+        } else {
+            find(line)
+        };
+
+        if binary {
+            // printing binary lines would only garble the terminal
+            if found.is_some() {
+                let binary = SearchLine::Binary {
+                    line_number,
+                    byte_offset: offset,
+                };
+                return send(binary, line_number, &mut last_sent);
+            }
+            continue;
+        }
 
-    for line in results {
-        println!("{line}");
+        if let Some(byte_range) = found {
+            selected += 1;
+
+            for (line_number, byte_offset, line) in before.drain(..) {
+                let context = SearchLine::Context {
+                    line_number,
+                    byte_offset,
+                    line: &line,
+                };
+                send(context, line_number, &mut last_sent)?;
+            }
+
+            let m = Match {
+                line_number,
+                byte_offset: offset,
+                byte_range,
+                line,
+            };
+            send(SearchLine::Match(m), line_number, &mut last_sent)?;
+            after_remaining = context.after;
+        } else if after_remaining > 0 {
+            let context = SearchLine::Context {
+                line_number,
+                byte_offset: offset,
+                line,
+            };
+            send(context, line_number, &mut last_sent)?;
+            after_remaining -= 1;
+        } else if context.before > 0 {
+            if before.len() == context.before {
+                before.pop_front();
+            }
+            before.push_back((line_number, offset, line.to_string()));
+        }
+    }
+}
+
+/// Search the whole of `reader` at once, so matches can span lines, sending a
+/// `SearchLine::Block` for each group of lines the matches touch.
+///
+/// Input is read up to its end before anything is sent, and invalid UTF-8 is replaced with
+/// U+FFFD, which byte offsets count as three bytes. Binary input is handled as `options.binary`
+/// says; `options.context` and `options.invert` don't apply.
+pub(crate) fn search_blocks<R, M, F>(
+    mut reader: R,
+    matcher: &M,
+    options: SearchOptions,
+    mut on_line: F,
+) -> io::Result<()>
+where
+    R: BufRead,
+    M: Matcher + ?Sized,
+    F: FnMut(SearchLine<'_>) -> io::Result<()>,
+{
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+
+    let binary = options.binary != BinaryFiles::Text && bytes.contains(&0);
+    if binary && options.binary == BinaryFiles::Skip {
+        return Ok(());
+    }
+
+    let contents = String::from_utf8_lossy(&bytes);
+    let blocks = search_multiline(matcher, &contents);
+    let max = options.max_count.unwrap_or(usize::MAX);
+
+    for block in blocks.into_iter().take(max) {
+        if binary {
+            // like `search_reader`, binary input is reported at its first match and no further
+            return on_line(SearchLine::Binary {
+                line_number: block.line_number,
+                byte_offset: block.byte_offset,
+            });
+        }
+
+        on_line(SearchLine::Block(block))?;
     }
 
     Ok(())
 }
 
-pub fn search_case_sensitive<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
-    // // based on the lifetime `'a`, the function should return a vector that contains references to string slices 
+/// Wrap `reader` so it yields UTF-8: UTF-16 input with a byte order mark and input in `encoding`
+/// are transcoded, anything else passes through untouched.
+pub fn decode<R: Read>(reader: R, encoding: Option<&'static Encoding>) -> impl Read {
+    DecodeReaderBytesBuilder::new()
+        .encoding(encoding)
+        .bom_override(true)
+        .strip_bom(true)
+        .utf8_passthru(true)
+        .build(reader)
+}
+
+/// Lines of `contents` with their 1-based number and the offset of their first byte.
+pub(crate) fn numbered_lines(contents: &str) -> NumberedLines<'_> {
+    NumberedLines {
+        lines: contents.split_inclusive('\n'),
+        line_number: 0,
+        byte_offset: 0,
+    }
+}
+
+/// The iterator returned by `numbered_lines`.
+pub(crate) struct NumberedLines<'a> {
+    lines: std::str::SplitInclusive<'a, char>,
+    line_number: usize,
+    byte_offset: usize,
+}
+
+impl<'a> Iterator for NumberedLines<'a> {
+    type Item = (usize, usize, &'a str);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let raw = self.lines.next()?;
+        let offset = self.byte_offset;
+        self.line_number += 1;
+        self.byte_offset += raw.len();
+
+        let line = match raw.strip_suffix('\n') {
+            Some(line) => line.strip_suffix('\r').unwrap_or(line),
+            None => raw,
+        };
+
+        Some((self.line_number, offset, line))
+    }
+}
+
+pub fn search_case_sensitive<'a>(query: &str, contents: &'a str) -> Vec<Match<'a>> {
+    // // based on the lifetime `'a`, the function should return a vector that contains references to string slices
     // // derived from the contents argument.
     // let mut results = Vec::new();
 
@@ -61,13 +1043,12 @@ pub fn search_case_sensitive<'a>(query: &str, contents: &'a str) -> Vec<&'a str>
 
     // results
 
-    contents
-        .lines()
-        .filter(|line| line.contains(query))
+    Searcher::default()
+        .iter(&Literal::new(query), contents)
         .collect()
 }
 
-pub fn search_case_insensitive<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
+pub fn search_case_insensitive<'a>(query: &str, contents: &'a str) -> Vec<Match<'a>> {
     // // calling `to_lowercase` creates a new string, rather than referencing the existing string, due to the nature of
     // // changing all characters to lowercase
     // let query = query.to_lowercase();
@@ -81,17 +1062,171 @@ pub fn search_case_insensitive<'a>(query: &str, contents: &'a str) -> Vec<&'a st
 
     // results
 
-    let query = query.to_lowercase();
+    // full Unicode case folding rather than `to_lowercase`, and no allocation for ASCII lines
+    let matcher = CaseInsensitive::new(query);
+
+    Searcher::default().iter(&matcher, contents).collect()
+}
+
+/// Lines within `max_distance` edits of `query`, each with the edit distance of its first match.
+pub fn search_fuzzy<'a>(
+    query: &str,
+    max_distance: usize,
+    ignore_case: bool,
+    contents: &'a str,
+) -> Vec<(usize, Match<'a>)> {
+    let matcher = PatternMatcher::fuzzy(query, max_distance, ignore_case, None);
+
+    numbered_lines(contents)
+        .filter_map(|(line_number, byte_offset, line)| {
+            let (distance, byte_range) = matcher.find_distance(line)?;
+
+            Some((
+                distance,
+                Match {
+                    line_number,
+                    byte_offset,
+                    byte_range,
+                    line,
+                },
+            ))
+        })
+        .collect()
+}
+
+pub fn search_regex<'a>(re: &Regex, contents: &'a str) -> Vec<Match<'a>> {
+    // every line is its own haystack, so `^` and `$` anchor to line boundaries
+    Searcher::default().iter(re, contents).collect()
+}
+
+/// Lines matching any of the patterns of `matcher`, each with the number of the pattern that
+/// matched first on the line.
+pub fn search_patterns<'a>(matcher: &PatternMatcher, contents: &'a str) -> Vec<(usize, Match<'a>)> {
+    numbered_lines(contents)
+        .filter_map(|(line_number, byte_offset, line)| {
+            let (pattern, byte_range) = matcher.find_pattern_at(line, 0)?;
+
+            Some((
+                pattern,
+                Match {
+                    line_number,
+                    byte_offset,
+                    byte_range,
+                    line,
+                },
+            ))
+        })
+        .collect()
+}
+
+/// Lines `expr` holds for, its terms matched literally.
+pub fn search_query<'a>(expr: &Expr, ignore_case: bool, contents: &'a str) -> Vec<Match<'a>> {
+    let matcher = PatternMatcher::query(expr.clone(), false, ignore_case, None)
+        .expect("literal terms always build");
+
+    Searcher::default().iter(&matcher, contents).collect()
+}
+
+/// Matches of `matcher` in the whole of `contents`, so they can span lines, grouped by the lines
+/// they touch. Matches that share a line end up in the same block.
+pub fn search_multiline<'a, M>(matcher: &M, contents: &'a str) -> Vec<Block<'a>>
+where
+    M: Matcher + ?Sized,
+{
+    let mut blocks: Vec<Block<'a>> = Vec::new();
+    let mut start = 0;
+    let mut line_number = 1;
 
+    for (pattern, range) in matcher.find_patterns(contents) {
+        // a match ending in a line terminator still ends on the line that terminator belongs to
+        let last = match range.is_empty() {
+            true => range.start,
+            false => range.end - 1,
+        };
+        let line_start = contents[..range.start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = contents[last..]
+            .find('\n')
+            .map_or(contents.len(), |i| last + i);
+
+        match blocks.last_mut() {
+            Some(block) if line_start < block.byte_offset + block.lines.len() + 1 => {
+                let end = line_end.max(block.byte_offset + block.lines.len());
+                block.lines = &contents[block.byte_offset..end];
+                block
+                    .matches
+                    .push((pattern, shift(range, block.byte_offset)));
+            }
+            _ => {
+                line_number += contents[start..line_start].matches('\n').count();
+                start = line_start;
+
+                blocks.push(Block {
+                    line_number,
+                    end_line_number: line_number,
+                    byte_offset: line_start,
+                    matches: vec![(pattern, shift(range, line_start))],
+                    lines: &contents[line_start..line_end],
+                });
+            }
+        }
+    }
+
+    for block in &mut blocks {
+        block.end_line_number = block.line_number + block.lines.matches('\n').count();
+
+        let lines = block.lines.strip_suffix('\r').unwrap_or(block.lines);
+        for (_, range) in &mut block.matches {
+            range.end = range.end.min(lines.len());
+            range.start = range.start.min(range.end);
+        }
+        block.lines = lines;
+    }
+
+    blocks
+}
+
+fn shift(range: Range<usize>, by: usize) -> Range<usize> {
+    range.start - by..range.end - by
+}
+
+/// Expand `template` with the capture groups of the first match on each matching line.
+pub fn format_regex(re: &Regex, template: &str, contents: &str) -> Vec<String> {
     contents
         .lines()
-        .filter(|line| line.to_lowercase().contains(&query))
+        .filter(|line| re.is_match(line))
+        .map(|line| matcher::expand_captures(re, template, line))
         .collect()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use matcher::find_case_sensitive;
+
+    fn lines<'a>(results: &[Match<'a>]) -> Vec<&'a str> {
+        results.iter().map(|m| m.line).collect()
+    }
+
+    fn render(contents: &str, options: SearchOptions) -> Vec<String> {
+        let mut results = Vec::new();
+        let find = |line: &str| find_case_sensitive("match", line);
+
+        search_reader(io::Cursor::new(contents), find, options, |line| {
+            results.push(match line {
+                SearchLine::Match(m) => format!("{}:{}", m.line_number, m.line),
+                SearchLine::Context {
+                    line_number, line, ..
+                } => format!("{line_number}-{line}"),
+                SearchLine::Break => String::from("--"),
+                SearchLine::Binary { line_number, .. } => format!("{line_number}: binary"),
+                SearchLine::Block(block) => format!("{}:{}", block.line_number, block.lines),
+            });
+            Ok(())
+        })
+        .unwrap();
+
+        results
+    }
 
     #[test]
     fn case_sensitive() {
@@ -103,7 +1238,12 @@ Rust:
 safe, fast, productive.
 Duct tape.";
 
-        assert_eq!(vec!["safe, fast, productive."], search_case_sensitive(query, contents));
+        let results = search_case_sensitive(query, contents);
+
+        assert_eq!(vec!["safe, fast, productive."], lines(&results));
+        assert_eq!(2, results[0].line_number);
+        assert_eq!(6, results[0].byte_offset);
+        assert_eq!(15..19, results[0].byte_range);
     }
 
     #[test]
@@ -114,7 +1254,373 @@ Rust:
 safe, fast, productive.
 Trust me.";
 
-        assert_eq!(vec!["Rust:", "Trust me."], search_case_insensitive(&query, &contents));
+        let results = search_case_insensitive(query, contents);
+
+        assert_eq!(vec!["Rust:", "Trust me."], lines(&results));
+        assert_eq!(
+            vec![1, 3],
+            results.iter().map(|m| m.line_number).collect::<Vec<_>>()
+        );
+        assert_eq!(1..5, results[1].byte_range);
+    }
+
+    #[test]
+    fn fuzzy_search() {
+        let contents = "\
+Rust:
+safe, fast, productive.
+Pick three.
+Trust me: ruts and rusty nails.";
+
+        let results: Vec<_> = search_fuzzy("rust", 1, true, contents)
+            .into_iter()
+            .map(|(distance, m)| (distance, m.line_number, &m.line[m.byte_range]))
+            .collect();
+
+        assert_eq!(vec![(0, 1, "Rust"), (0, 4, "rust")], results);
+
+        let results = search_fuzzy("prodcutive", 2, false, contents);
+        assert_eq!(
+            vec![(2, 2)],
+            results
+                .iter()
+                .map(|(d, m)| (*d, m.line_number))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn query_expression() {
+        let contents = "\
+timeout talking to db
+timeout in cache, retry 1
+db timeout, retry 2
+no timeout";
+
+        let expr = query::parse("TIMEOUT AND (db OR cache) NOT retry").unwrap();
+        let results: Vec<_> = search_query(&expr, true, contents)
+            .into_iter()
+            .map(|m| (m.line_number, &m.line[m.byte_range]))
+            .collect();
+
+        assert_eq!(vec![(1, "timeout")], results);
+
+        // a line selected only by what it doesn't contain matches emptily at its start
+        let expr = query::parse("NOT timeout").unwrap();
+        assert_eq!(Vec::<Match>::new(), search_query(&expr, false, contents));
+        let expr = query::parse("NOT \"in cache\"").unwrap();
+        assert_eq!(
+            vec![(1, 0..0), (3, 0..0), (4, 0..0)],
+            search_query(&expr, false, contents)
+                .into_iter()
+                .map(|m| (m.line_number, m.byte_range))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn matches_spanning_lines() {
+        let contents = "\
+fn search(
+    query: &str,
+) {}
+fn run() {}\r
+fn main() {}";
+        let re = RegexBuilder::new(r"fn \w+\(\n\s*\w+|\(\) \{\}$")
+            .multi_line(true)
+            .crlf(true)
+            .build()
+            .unwrap();
+        let matcher = PatternMatcher::regex(re, None);
+
+        let blocks = search_multiline(&matcher, contents);
+        let spans: Vec<_> = blocks
+            .iter()
+            .map(|block| (block.line_number, block.end_line_number, block.lines))
+            .collect();
+
+        assert_eq!(
+            vec![
+                (1, 2, "fn search(\n    query: &str,"),
+                (4, 4, "fn run() {}"),
+                (5, 5, "fn main() {}"),
+            ],
+            spans
+        );
+        assert_eq!(vec![(0, 0..20)], blocks[0].matches);
+
+        // matches sharing a line are one block
+        let matcher = PatternMatcher::literal(",\n) {", false, None);
+        let blocks = search_multiline(&matcher, contents);
+        assert_eq!((2, 3), (blocks[0].line_number, blocks[0].end_line_number));
+        assert_eq!("    query: &str,\n) {}", blocks[0].lines);
+    }
+
+    #[test]
+    fn large_non_ascii_multiline_input() {
+        // the whole input is one haystack, which must be folded once, not once per match
+        let contents = "café match here\n".repeat(5_000);
+
+        for normalization in [None, Some(unicode::Normalization::Nfc)] {
+            let matcher = PatternMatcher::literal("MATCH", true, normalization);
+            let blocks = search_multiline(&matcher, &contents);
+
+            assert_eq!(5_000, blocks.len());
+            assert_eq!(5_000, blocks[4_999].line_number);
+            assert_eq!(vec![(0, 6..11)], blocks[4_999].matches);
+        }
+    }
+
+    #[test]
+    fn binary_input() {
+        let contents = "nothing\0here\nmatch\n";
+        let options = |binary| SearchOptions {
+            binary,
+            ..SearchOptions::default()
+        };
+
+        assert_eq!(
+            vec!["2: binary"],
+            render(contents, options(BinaryFiles::Report))
+        );
+        assert!(render(contents, options(BinaryFiles::Skip)).is_empty());
+        assert_eq!(
+            vec!["2:match"],
+            render(contents, options(BinaryFiles::Text))
+        );
+    }
+
+    #[test]
+    fn invalid_utf8_is_searched_lossily() {
+        let reader = io::Cursor::new(b"caf\xe9 match\nmatch\n".to_vec());
+        let mut results = Vec::new();
+
+        search_reader(
+            reader,
+            |line| find_case_sensitive("match", line),
+            SearchOptions::default(),
+            |line| {
+                if let SearchLine::Match(m) = line {
+                    results.push((m.byte_offset, m.line.to_string()));
+                }
+                Ok(())
+            },
+        )
+        .unwrap();
+
+        // offsets count the bytes read, not the replacement characters
+        assert_eq!(
+            vec![
+                (0, String::from("caf\u{FFFD} match")),
+                (11, String::from("match"))
+            ],
+            results
+        );
+    }
+
+    #[test]
+    fn decode_byte_order_marks_and_encodings() {
+        let read = |bytes: &[u8], encoding| {
+            let mut text = String::new();
+            decode(bytes, encoding).read_to_string(&mut text).unwrap();
+            text
+        };
+
+        assert_eq!("hi", read(b"\xff\xfeh\0i\0", None));
+        assert_eq!("hi", read(b"\xfe\xff\0h\0i", None));
+        assert_eq!("café", read(b"caf\xe9", Some(encoding_rs::WINDOWS_1252)));
+        assert_eq!("café", read("café".as_bytes(), None));
+    }
+
+    #[test]
+    fn stream_from_reader() {
+        let reader = io::Cursor::new("Rust:\r\nsafe, fast, productive.\nTrust me.");
+        let mut results = Vec::new();
+
+        search_reader(
+            reader,
+            |line| find_case_sensitive("ust", line),
+            SearchOptions::default(),
+            |line| {
+                if let SearchLine::Match(m) = line {
+                    results.push((m.line_number, m.byte_offset, m.line.to_string()));
+                }
+                Ok(())
+            },
+        )
+        .unwrap();
+
+        assert_eq!(
+            vec![
+                (1, 0, String::from("Rust:")),
+                (3, 31, String::from("Trust me."))
+            ],
+            results
+        );
+    }
+
+    #[test]
+    fn context_windows_merge() {
+        let contents = "a\nmatch\nb\nc\nmatch\nd\ne\nf\ng\nmatch\nh";
+        let options = SearchOptions {
+            context: Context {
+                before: 1,
+                after: 1,
+            },
+            ..SearchOptions::default()
+        };
+
+        assert_eq!(
+            vec!["1-a", "2:match", "3-b", "4-c", "5:match", "6-d", "--", "9-g", "10:match", "11-h"],
+            render(contents, options)
+        );
+    }
+
+    #[test]
+    fn invert_selects_other_lines() {
+        let options = SearchOptions {
+            invert: true,
+            ..SearchOptions::default()
+        };
+
+        assert_eq!(vec!["2:a", "4:b"], render("match\na\nmatch\nb", options));
+    }
+
+    #[test]
+    fn max_count_keeps_trailing_context() {
+        let options = SearchOptions {
+            context: Context {
+                before: 0,
+                after: 1,
+            },
+            max_count: Some(1),
+            ..SearchOptions::default()
+        };
+
+        assert_eq!(
+            vec!["1:match", "2-match"],
+            render("match\nmatch\nmatch", options)
+        );
+    }
+
+    #[test]
+    fn no_breaks_without_context() {
+        let contents = "match\na\nmatch";
+
+        assert_eq!(
+            vec!["1:match", "3:match"],
+            render(contents, SearchOptions::default())
+        );
+    }
+
+    #[test]
+    fn case_insensitive_range_in_original_line() {
+        // `İ` lowercases to two characters, so offsets in the lowered line would be off by one
+        let matcher = PatternMatcher::literal("rust", true, None);
+
+        assert_eq!(Some(3..7), matcher.find("İ rUsT"));
+    }
+
+    #[test]
+    fn stdin_when_no_path() {
+        let args = ["minigrep", "rust"].iter().map(|arg| arg.to_string());
+        let config = Config::build(args).unwrap();
+
+        assert_eq!(vec!["-"], config.paths);
+    }
+
+    #[test]
+    fn regex_anchors_and_classes() {
+        let re = Regex::new(r"^fn \w+").unwrap();
+        let contents = "\
+fn main() {
+    fn nested() {}
+let f = fn_ptr;";
+
+        assert_eq!(vec!["fn main() {"], lines(&search_regex(&re, contents)));
+    }
+
+    #[test]
+    fn regex_alternation_and_repetition() {
+        let re = Regex::new(r"ERROR\s+\d{3}|WARN").unwrap();
+        let contents = "\
+ERROR  404 not found
+ERROR 12 short code
+WARN disk almost full";
+
+        assert_eq!(
+            vec!["ERROR  404 not found", "WARN disk almost full"],
+            lines(&search_regex(&re, contents))
+        );
+    }
+
+    #[test]
+    fn regex_case_insensitive() {
+        let config = Config {
+            query: String::from("^rust"),
+            ignore_case: true,
+            regex: true,
+            ..Config::default()
+        };
+        let re = config.build_regex().unwrap();
+        let contents = "\
+Rust:
+Trust me.";
+
+        assert_eq!(vec!["Rust:"], lines(&search_regex(&re, contents)));
+    }
+
+    #[test]
+    fn regex_capture_groups() {
+        let re = Regex::new(r"(?P<level>[A-Z]+): (\w+)").unwrap();
+        let contents = "\
+INFO: started
+nothing here
+ERROR: crashed";
+
+        assert_eq!(
+            vec!["started [INFO]", "crashed [ERROR]"],
+            format_regex(&re, "$2 [${level}]", contents)
+        );
+    }
+
+    #[test]
+    fn any_of_several_patterns() {
+        let patterns = vec![String::from("duct"), String::from("Pick")];
+        let matcher = PatternMatcher::literals(&patterns, false, None).unwrap();
+        let contents = "\
+Rust:
+safe, fast, productive.
+Pick three.
+Duct tape.";
+
+        let results = search_patterns(&matcher, contents);
+
+        assert_eq!(
+            vec![(0, "safe, fast, productive."), (1, "Pick three.")],
+            results
+                .iter()
+                .map(|(pattern, m)| (*pattern, m.line))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn patterns_from_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("patterns.txt");
+        fs::write(&file, "rust\ntrust\n").unwrap();
+
+        let config = Config {
+            patterns: vec![String::from("safe")],
+            pattern_files: vec![file.to_str().unwrap().to_string()],
+            ..Config::default()
+        };
+
+        assert_eq!(
+            vec!["safe", "rust", "trust"],
+            config.load_patterns().unwrap()
+        );
     }
 }
 
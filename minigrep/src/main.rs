@@ -1,20 +1,43 @@
 use std::env;
 use std::process;
 
-use minigrep::Config;
+use minigrep::{Config, ConfigError};
 
 fn main() {
     let args: Vec<String> = env::args().collect();
 
-    let config = Config::build(env::args()).unwrap_or_else(|err| {
+    // `minigrep index build <DIR>...` maintains a search index instead of searching
+    if minigrep::args::is_index_command(&args) {
+        match minigrep::index::command(args.into_iter().skip(2)) {
+            Ok(report) => print!("{report}"),
+            Err(e) => {
+                eprintln!("Problem building the index: {e}");
+                process::exit(2);
+            }
+        }
+        return;
+    }
+
+    let config = Config::build(args.into_iter()).unwrap_or_else(|err| {
+        // `--help` and `--version` arrive as errors so parsing stops there, but they aren't failures
+        if let ConfigError::Help | ConfigError::Version = err {
+            println!("{err}");
+            process::exit(0);
+        }
+
         eprintln!("Problem parsing arguments: {err}");
-        process::exit(1);
+        eprintln!("Try 'minigrep --help' for more information.");
+        process::exit(2);
     });
-    
+
     // `if let` checks that the return value of an expression matches the specified value of the signature.
     // associated data can be used within the expression's scope.
-    if let Err(e) = minigrep::run(config) {
-        eprintln!("Application error: {e}");
-        process::exit(1);
+    // exit codes follow grep: 0 when something was selected, 1 when nothing was, 2 on errors
+    match minigrep::run(config) {
+        Ok(outcome) => process::exit(outcome.exit_code()),
+        Err(e) => {
+            eprintln!("Application error: {e}");
+            process::exit(2);
+        }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,725 @@
+use std::{cmp::Reverse, error::Error, ops::Range};
+
+use aho_corasick::{AhoCorasick, MatchKind};
+use regex::{Regex, RegexBuilder};
+use regex_automata::{meta, util::syntax, Input};
+
+use crate::fuzzy::Fuzzy;
+use crate::query::Expr;
+use crate::unicode::{self, find_ascii_case_insensitive, Mapped, Normalization};
+use crate::Config;
+
+/// `Matcher` trait
+///
+/// Finds matches in a haystack, normally a single line. Only `find_at` has to be implemented; a
+/// matcher with several patterns also implements `find_pattern_at` to tell which one matched.
+pub trait Matcher {
+    /// Range of the first match in `haystack` that starts at or after `start`.
+    fn find_at(&self, haystack: &str, start: usize) -> Option<Range<usize>>;
+
+    /// Range of the first match in `haystack`.
+    fn find(&self, haystack: &str) -> Option<Range<usize>> {
+        self.find_at(haystack, 0)
+    }
+
+    /// Number of the pattern and range of the first match in `haystack` that starts at or after
+    /// `start`. Matchers with a single pattern always report pattern 0.
+    fn find_pattern_at(&self, haystack: &str, start: usize) -> Option<(usize, Range<usize>)> {
+        self.find_at(haystack, start).map(|range| (0, range))
+    }
+
+    /// Ranges of every non-overlapping match in `haystack`.
+    fn find_iter(&self, haystack: &str) -> Vec<Range<usize>> {
+        self.find_patterns(haystack)
+            .into_iter()
+            .map(|(_, range)| range)
+            .collect()
+    }
+
+    /// Pattern numbers and ranges of every non-overlapping match in `haystack`.
+    ///
+    /// Matchers that transform the haystack before searching it override this to transform it
+    /// only once.
+    fn find_patterns(&self, haystack: &str) -> Vec<(usize, Range<usize>)> {
+        find_all(haystack, |start| self.find_pattern_at(haystack, start))
+    }
+}
+
+/// Every non-overlapping match in `haystack`, with `find_pattern_at` finding the first one that
+/// starts at or after a given offset.
+fn find_all(
+    haystack: &str,
+    mut find_pattern_at: impl FnMut(usize) -> Option<(usize, Range<usize>)>,
+) -> Vec<(usize, Range<usize>)> {
+    let mut ranges = Vec::new();
+    let mut start = 0;
+    let mut last_end = None;
+
+    while start <= haystack.len() {
+        let Some((pattern, range)) = find_pattern_at(start) else {
+            break;
+        };
+
+        if range.is_empty() {
+            // step past empty matches so the loop always makes progress
+            start = match haystack[range.end..].chars().next() {
+                Some(c) => range.end + c.len_utf8(),
+                None => haystack.len() + 1,
+            };
+
+            // like `Regex::find_iter`, skip an empty match right after the previous match
+            if last_end == Some(range.start) {
+                continue;
+            }
+        } else {
+            start = range.end;
+        }
+
+        last_end = Some(range.end);
+        ranges.push((pattern, range));
+    }
+
+    ranges
+}
+
+/// `PatternMatcher` struct and implementations
+///
+/// The patterns as the search actually runs them, plus the normalization applied to every line.
+#[derive(Debug)]
+pub struct PatternMatcher {
+    kind: Kind,
+    normalization: Option<Normalization>,
+    /// The patterns as given, indexed by the pattern numbers matches report.
+    patterns: Vec<String>,
+}
+
+#[derive(Debug)]
+enum Kind {
+    CaseSensitive(String),
+    /// Holds the query already case folded.
+    CaseInsensitive(String),
+    Regex(Regex),
+    /// Several literal patterns, already transformed, searched for in one pass.
+    Literals {
+        automaton: AhoCorasick,
+        fold: bool,
+    },
+    /// Several regex patterns compiled into one automaton.
+    Regexes(meta::Regex),
+    /// Holds the query already transformed.
+    Fuzzy {
+        fuzzy: Fuzzy,
+        fold: bool,
+    },
+    /// A boolean expression whose terms are numbers of the matchers in `terms`.
+    Query {
+        expr: Expr<usize>,
+        terms: Vec<PatternMatcher>,
+    },
+}
+
+impl PatternMatcher {
+    /// Build the matcher for `patterns`, normally the result of `Config::load_patterns`.
+    pub fn new(config: &Config, patterns: &[String]) -> Result<PatternMatcher, Box<dyn Error>> {
+        let normalization = config.normalization;
+
+        // an expression brings its own patterns, its terms
+        if let Some(expr) = &config.query_expr {
+            let matcher = PatternMatcher::query(
+                expr.clone(),
+                config.regex,
+                config.ignore_case,
+                normalization,
+            )?;
+            return Ok(matcher);
+        }
+
+        let matcher = match patterns {
+            [pattern] if config.fuzzy.is_some() => {
+                let max_distance = config.fuzzy.unwrap_or_default();
+                PatternMatcher::fuzzy(pattern, max_distance, config.ignore_case, normalization)
+            }
+            // with --multiline the whole input is one haystack, so `^` and `$` are made to match
+            // at every line, as they do when lines are searched one by one
+            [pattern] if config.regex => PatternMatcher::regex(
+                RegexBuilder::new(pattern)
+                    .case_insensitive(config.ignore_case)
+                    .multi_line(config.multiline)
+                    .crlf(config.multiline)
+                    .build()?,
+                normalization,
+            ),
+            [pattern] => PatternMatcher::literal(pattern, config.ignore_case, normalization),
+            _ if config.regex => PatternMatcher::regexes(
+                patterns,
+                config.ignore_case,
+                config.multiline,
+                normalization,
+            )?,
+            _ => PatternMatcher::literals(patterns, config.ignore_case, normalization)?,
+        };
+
+        Ok(matcher)
+    }
+
+    /// Match `query` literally, case folded when `ignore_case` is set.
+    pub fn literal(
+        query: &str,
+        ignore_case: bool,
+        normalization: Option<Normalization>,
+    ) -> PatternMatcher {
+        // the query is only transformed once, not once per line
+        let transformed = unicode::transform(query, ignore_case, normalization);
+
+        let kind = if ignore_case {
+            Kind::CaseInsensitive(transformed)
+        } else {
+            Kind::CaseSensitive(transformed)
+        };
+
+        PatternMatcher {
+            kind,
+            normalization,
+            patterns: vec![query.to_string()],
+        }
+    }
+
+    pub fn regex(re: Regex, normalization: Option<Normalization>) -> PatternMatcher {
+        PatternMatcher {
+            patterns: vec![re.as_str().to_string()],
+            kind: Kind::Regex(re),
+            normalization,
+        }
+    }
+
+    /// Match `query` with up to `max_distance` edits, case folded when `ignore_case` is set.
+    pub fn fuzzy(
+        query: &str,
+        max_distance: usize,
+        ignore_case: bool,
+        normalization: Option<Normalization>,
+    ) -> PatternMatcher {
+        let transformed = unicode::transform(query, ignore_case, normalization);
+
+        PatternMatcher {
+            kind: Kind::Fuzzy {
+                fuzzy: Fuzzy::new(&transformed, max_distance),
+                fold: ignore_case,
+            },
+            normalization,
+            patterns: vec![query.to_string()],
+        }
+    }
+
+    /// Select the lines `expr` holds for, matching each of its terms as a regex or literally.
+    ///
+    /// The pattern number of a match is the position of its term in the expression.
+    pub fn query(
+        expr: Expr,
+        regex: bool,
+        ignore_case: bool,
+        normalization: Option<Normalization>,
+    ) -> Result<PatternMatcher, regex::Error> {
+        let patterns = expr.terms().into_iter().cloned().collect();
+        let mut terms = Vec::new();
+
+        let expr = expr.map(&mut |term| {
+            let matcher = if regex {
+                let re = RegexBuilder::new(&term)
+                    .case_insensitive(ignore_case)
+                    .build()?;
+                PatternMatcher::regex(re, normalization)
+            } else {
+                PatternMatcher::literal(&term, ignore_case, normalization)
+            };
+
+            terms.push(matcher);
+            Ok(terms.len() - 1)
+        })?;
+
+        Ok(PatternMatcher {
+            kind: Kind::Query { expr, terms },
+            normalization,
+            patterns,
+        })
+    }
+
+    /// Does this matcher report edit distances other than 0?
+    pub fn is_fuzzy(&self) -> bool {
+        matches!(self.kind, Kind::Fuzzy { .. })
+    }
+
+    /// Edit distance and range of the first match in `line`; exact matchers always report 0.
+    pub fn find_distance(&self, line: &str) -> Option<(usize, Range<usize>)> {
+        match &self.kind {
+            Kind::Fuzzy { fuzzy, fold } => self.find_fuzzy(fuzzy, *fold, line),
+            _ => self.find(line).map(|range| (0, range)),
+        }
+    }
+
+    fn find_fuzzy(&self, fuzzy: &Fuzzy, fold: bool, line: &str) -> Option<(usize, Range<usize>)> {
+        if !fold && self.normalization.is_none() {
+            return fuzzy.find(line).map(|(range, distance)| (distance, range));
+        }
+
+        let mapped = Mapped::new(line, fold, self.normalization);
+        fuzzy
+            .find(&mapped.text)
+            .map(|(range, distance)| (distance, mapped.original(range)))
+    }
+
+    /// Match any of `patterns` literally, in a single pass over each line.
+    ///
+    /// Where several patterns match at the same position, the longest one wins.
+    pub fn literals(
+        patterns: &[String],
+        ignore_case: bool,
+        normalization: Option<Normalization>,
+    ) -> Result<PatternMatcher, aho_corasick::BuildError> {
+        let transformed = patterns
+            .iter()
+            .map(|pattern| unicode::transform(pattern, ignore_case, normalization));
+
+        // folded patterns are lowercase, so ASCII case insensitivity lets ASCII lines skip folding
+        let automaton = AhoCorasick::builder()
+            .match_kind(MatchKind::LeftmostLongest)
+            .ascii_case_insensitive(ignore_case)
+            .build(transformed)?;
+
+        Ok(PatternMatcher {
+            kind: Kind::Literals {
+                automaton,
+                fold: ignore_case,
+            },
+            normalization,
+            patterns: patterns.to_vec(),
+        })
+    }
+
+    /// Match any of the regex `patterns`, in a single pass over each line.
+    ///
+    /// With `multi_line`, `^` and `$` also match at line terminators within the haystack.
+    pub fn regexes(
+        patterns: &[String],
+        ignore_case: bool,
+        multi_line: bool,
+        normalization: Option<Normalization>,
+    ) -> Result<PatternMatcher, Box<meta::BuildError>> {
+        let syntax = syntax::Config::new()
+            .case_insensitive(ignore_case)
+            .multi_line(multi_line)
+            .crlf(multi_line);
+        let re = meta::Regex::builder()
+            .syntax(syntax)
+            .build_many(patterns)
+            .map_err(Box::new)?;
+
+        Ok(PatternMatcher {
+            kind: Kind::Regexes(re),
+            normalization,
+            patterns: patterns.to_vec(),
+        })
+    }
+
+    /// The patterns as given; a match's pattern number indexes into this.
+    pub fn patterns(&self) -> &[String] {
+        &self.patterns
+    }
+
+    /// `line` with every match replaced by `template`, expanded for each match.
+    pub fn replace_all(&self, template: &str, line: &str) -> String {
+        let mut replaced = String::with_capacity(line.len());
+        let mut last = 0;
+
+        for range in self.find_iter(line) {
+            replaced.push_str(&line[last..range.start]);
+            replaced.push_str(&self.expand_match(template, line, range.clone()));
+            last = range.end;
+        }
+
+        replaced.push_str(&line[last..]);
+        replaced
+    }
+
+    /// Expand `template` for the match at `range` in `line`: for a regex, `$1` or `${name}` insert
+    /// its capture groups; the other matchers use `template` as it is.
+    pub fn expand_match(&self, template: &str, line: &str, range: Range<usize>) -> String {
+        let mut expanded = String::new();
+
+        // normalized lines are matched as different text, so only the match itself is captured
+        let (haystack, start) = match self.normalization {
+            None => (line, range.start),
+            Some(_) => (&line[range], 0),
+        };
+
+        match &self.kind {
+            Kind::Regex(re) => {
+                if let Some(caps) = re.captures_at(haystack, start) {
+                    caps.expand(template, &mut expanded);
+                }
+            }
+            Kind::Regexes(re) => {
+                let mut caps = re.create_captures();
+                re.search_captures(&Input::new(haystack).range(start..), &mut caps);
+                caps.interpolate_string_into(haystack, template, &mut expanded);
+            }
+            _ => expanded.push_str(template),
+        }
+
+        expanded
+    }
+
+    /// Expand `template` with the capture groups of the first match in `line`.
+    ///
+    /// Only regexes have capture groups; the other matchers return `line` unchanged.
+    pub fn expand(&self, template: &str, line: &str) -> String {
+        match &self.kind {
+            Kind::Regex(re) => expand_captures(re, template, line),
+            Kind::Regexes(re) => {
+                let mut caps = re.create_captures();
+                let mut expanded = String::new();
+
+                re.captures(line, &mut caps);
+                if caps.is_match() {
+                    caps.interpolate_string_into(line, template, &mut expanded);
+                }
+
+                expanded
+            }
+            _ => line.to_string(),
+        }
+    }
+}
+
+impl PatternMatcher {
+    /// `line` transformed the way it has to be searched, or `None` when it is searched as is.
+    fn mapped(&self, line: &str) -> Option<Mapped> {
+        let fold = match &self.kind {
+            Kind::CaseSensitive(_) | Kind::Regex(_) | Kind::Regexes(_) => false,
+            Kind::CaseInsensitive(_) => true,
+            Kind::Literals { fold, .. } | Kind::Fuzzy { fold, .. } => *fold,
+            // the terms transform the line themselves
+            Kind::Query { .. } => return None,
+        };
+
+        // ASCII is unchanged by normalization and folds to ASCII, which all but the fuzzy
+        // matcher can find without folding
+        let ascii_as_is = !matches!(self.kind, Kind::Fuzzy { .. });
+        if (!fold && self.normalization.is_none()) || (ascii_as_is && line.is_ascii()) {
+            return None;
+        }
+
+        Some(Mapped::new(line, fold, self.normalization))
+    }
+
+    /// `find_pattern_at` in the text of `mapped`, where `start` and the range found are offsets.
+    fn find_mapped_at(&self, mapped: &Mapped, start: usize) -> Option<(usize, Range<usize>)> {
+        let text = mapped.text.as_str();
+        let shift = |range: Range<usize>| range.start + start..range.end + start;
+
+        match &self.kind {
+            Kind::CaseSensitive(query) | Kind::CaseInsensitive(query) => {
+                find_case_sensitive(query, &text[start..]).map(|r| (0, shift(r)))
+            }
+            Kind::Regex(re) => re.find_at(text, start).map(|m| (0, m.range())),
+            Kind::Literals { automaton, .. } => automaton
+                .find(&text[start..])
+                .map(|m| (m.pattern().as_usize(), shift(m.range()))),
+            Kind::Fuzzy { fuzzy, .. } => fuzzy
+                .find(&text[start..])
+                .map(|(range, _)| (0, shift(range))),
+            Kind::Regexes(re) => re
+                .search(&Input::new(text).range(start..))
+                .map(|m| (m.pattern().as_usize(), m.range())),
+            Kind::Query { .. } => None,
+        }
+    }
+}
+
+impl Matcher for PatternMatcher {
+    fn find_at(&self, line: &str, start: usize) -> Option<Range<usize>> {
+        self.find_pattern_at(line, start).map(|(_, range)| range)
+    }
+
+    fn find_pattern_at(&self, line: &str, start: usize) -> Option<(usize, Range<usize>)> {
+        if let Some(mapped) = self.mapped(line) {
+            return self
+                .find_mapped_at(&mapped, mapped.position(start))
+                .map(|(pattern, range)| (pattern, mapped.original(range)));
+        }
+
+        let shift = |range: Range<usize>| range.start + start..range.end + start;
+
+        match &self.kind {
+            Kind::CaseSensitive(query) => {
+                find_case_sensitive(query, &line[start..]).map(|r| (0, shift(r)))
+            }
+            // only ASCII lines are searched as is
+            Kind::CaseInsensitive(query) => {
+                find_ascii_case_insensitive(query, &line[start..]).map(|r| (0, shift(r)))
+            }
+            // `find_at` rather than slicing keeps `^` and `\b` aware of what comes before `start`
+            Kind::Regex(re) => re.find_at(line, start).map(|m| (0, m.range())),
+            Kind::Literals { automaton, .. } => automaton
+                .find(&line[start..])
+                .map(|m| (m.pattern().as_usize(), shift(m.range()))),
+            Kind::Fuzzy { fuzzy, .. } => fuzzy
+                .find(&line[start..])
+                .map(|(range, _)| (0, shift(range))),
+            Kind::Query { expr, terms } => {
+                // the whole line decides whether it's selected, the terms that aren't negated
+                // where it matches
+                if !expr.eval(&|&term| terms[term].find(line).is_some()) {
+                    return None;
+                }
+
+                let first = expr
+                    .positive_terms()
+                    .into_iter()
+                    .filter_map(|&term| Some((term, terms[term].find_at(line, start)?)))
+                    .min_by_key(|(_, range)| (range.start, Reverse(range.end)));
+
+                // a line selected only by what it lacks matches emptily at its start, like an
+                // inverted match
+                match first {
+                    None if start == 0 => Some((0, 0..0)),
+                    first => first,
+                }
+            }
+            Kind::Regexes(re) => re
+                .search(&Input::new(line).range(start..))
+                .map(|m| (m.pattern().as_usize(), m.range())),
+        }
+    }
+
+    fn find_patterns(&self, line: &str) -> Vec<(usize, Range<usize>)> {
+        // a haystack of a whole file under `-U` would otherwise be transformed again for every
+        // match
+        match self.mapped(line) {
+            Some(mapped) => find_all(line, |start| {
+                self.find_mapped_at(&mapped, mapped.position(start))
+                    .map(|(pattern, range)| (pattern, mapped.original(range)))
+            }),
+            None => find_all(line, |start| self.find_pattern_at(line, start)),
+        }
+    }
+}
+
+/// `Literal` struct and implementations
+///
+/// A query matched byte for byte.
+#[derive(Debug, Clone)]
+pub struct Literal {
+    query: String,
+}
+
+impl Literal {
+    pub fn new(query: &str) -> Literal {
+        Literal {
+            query: query.to_string(),
+        }
+    }
+}
+
+impl Matcher for Literal {
+    fn find_at(&self, haystack: &str, start: usize) -> Option<Range<usize>> {
+        find_case_sensitive(&self.query, &haystack[start..])
+            .map(|range| range.start + start..range.end + start)
+    }
+}
+
+/// `CaseInsensitive` struct and implementations
+///
+/// A query matched with full Unicode case folding, so `ß` matches `SS`.
+#[derive(Debug, Clone)]
+pub struct CaseInsensitive {
+    /// The query already case folded.
+    folded: String,
+}
+
+impl CaseInsensitive {
+    pub fn new(query: &str) -> CaseInsensitive {
+        CaseInsensitive {
+            folded: unicode::transform(query, true, None),
+        }
+    }
+}
+
+impl Matcher for CaseInsensitive {
+    fn find_at(&self, haystack: &str, start: usize) -> Option<Range<usize>> {
+        find_literal(&self.folded, &haystack[start..], true, None)
+            .map(|range| range.start + start..range.end + start)
+    }
+
+    fn find_patterns(&self, haystack: &str) -> Vec<(usize, Range<usize>)> {
+        if haystack.is_ascii() {
+            return find_all(haystack, |start| self.find_pattern_at(haystack, start));
+        }
+
+        // folded once rather than once per match
+        let mapped = Mapped::new(haystack, true, None);
+        find_all(haystack, |start| {
+            let start = mapped.position(start);
+            let range = find_case_sensitive(&self.folded, &mapped.text[start..])?;
+            Some((0, mapped.original(range.start + start..range.end + start)))
+        })
+    }
+}
+
+impl Matcher for Regex {
+    // `Regex::find_at` rather than slicing keeps `^` and `\b` aware of what comes before `start`
+    fn find_at(&self, haystack: &str, start: usize) -> Option<Range<usize>> {
+        Regex::find_at(self, haystack, start).map(|m| m.range())
+    }
+}
+
+impl Matcher for Fuzzy {
+    fn find_at(&self, haystack: &str, start: usize) -> Option<Range<usize>> {
+        Fuzzy::find(self, &haystack[start..])
+            .map(|(range, _)| range.start + start..range.end + start)
+    }
+}
+
+pub(crate) fn find_case_sensitive(query: &str, line: &str) -> Option<Range<usize>> {
+    line.find(query).map(|start| start..start + query.len())
+}
+
+/// Find the already transformed `query` in `line`, returning the range in the original `line`.
+fn find_literal(
+    query: &str,
+    line: &str,
+    fold: bool,
+    normalization: Option<Normalization>,
+) -> Option<Range<usize>> {
+    // ASCII is unchanged by normalization and folds to ASCII, so most lines need no allocation
+    if line.is_ascii() && fold {
+        return find_ascii_case_insensitive(query, line);
+    }
+
+    if line.is_ascii() || (!fold && normalization.is_none()) {
+        return find_case_sensitive(query, line);
+    }
+
+    let mapped = Mapped::new(line, fold, normalization);
+    let start = mapped.text.find(query)?;
+
+    Some(mapped.original(start..start + query.len()))
+}
+
+pub(crate) fn expand_captures(re: &Regex, template: &str, line: &str) -> String {
+    let mut expanded = String::new();
+
+    if let Some(caps) = re.captures(line) {
+        caps.expand(template, &mut expanded);
+    }
+
+    expanded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_every_match() {
+        let matcher = PatternMatcher::literal("AB", true, None);
+
+        assert_eq!(vec![0..2, 3..5, 6..8], matcher.find_iter("ab AB aB"));
+    }
+
+    #[test]
+    fn empty_matches_make_progress() {
+        let matcher = PatternMatcher::regex(Regex::new("x*").unwrap(), None);
+
+        assert_eq!(vec![0..0, 1..3, 5..5], matcher.find_iter("axxé"));
+    }
+
+    #[test]
+    fn folded_matches_in_original_line() {
+        let matcher = PatternMatcher::literal("STRASSE", true, None);
+
+        assert_eq!(vec![0..7, 12..19], matcher.find_iter("Straße und strasse"));
+    }
+
+    #[test]
+    fn normalized_regex() {
+        let matcher = PatternMatcher::regex(Regex::new("café$").unwrap(), Some(Normalization::Nfc));
+
+        assert_eq!(Some(3..9), matcher.find("Le cafe\u{301}"));
+    }
+
+    #[test]
+    fn report_which_pattern_matched() {
+        let patterns = [
+            "rust".to_string(),
+            "trust".to_string(),
+            "STRASSE".to_string(),
+        ];
+        let matcher = PatternMatcher::literals(&patterns, true, None).unwrap();
+
+        // the longest pattern wins at a position, and folding still applies to non-ASCII lines
+        assert_eq!(
+            vec![(1, 0..5), (0, 9..13), (2, 14..21)],
+            matcher.find_patterns("Trust in Rust Straße")
+        );
+    }
+
+    #[test]
+    fn several_regexes_in_one_pass() {
+        let patterns = [r"\d+".to_string(), r"(\w+)@(\w+)".to_string()];
+        let matcher = PatternMatcher::regexes(&patterns, false, false, None).unwrap();
+
+        assert_eq!(
+            vec![(1, 0..8), (0, 13..15)],
+            matcher.find_patterns("ann@host for 42")
+        );
+        assert_eq!("host", matcher.expand("$2", "ann@host"));
+    }
+
+    #[test]
+    fn fuzzy_ignoring_case() {
+        let matcher = PatternMatcher::fuzzy("STRASSE", 1, true, None);
+
+        assert!(matcher.is_fuzzy());
+        assert_eq!(Some((1, 4..10)), matcher.find_distance("die Strase"));
+        // folding happens before distances are counted, so `ß` is two characters
+        assert_eq!(Some((0, 4..11)), matcher.find_distance("die Straße"));
+        assert_eq!(
+            Some((0, 1..5)),
+            PatternMatcher::literal("rust", false, None).find_distance("trust")
+        );
+    }
+
+    #[test]
+    fn query_terms_as_patterns() {
+        let expr = crate::query::parse(r#"ERROR (\d+ms OR "time out") NOT retry"#).unwrap();
+        let matcher = PatternMatcher::query(expr, true, false, None).unwrap();
+
+        assert_eq!(
+            vec!["ERROR", r"\d+ms", "time out", "retry"],
+            matcher.patterns()
+        );
+        // only the terms that aren't negated are reported, each with its number
+        assert_eq!(
+            vec![(0, 0..5), (1, 12..17)],
+            matcher.find_patterns("ERROR: took 150ms")
+        );
+        assert_eq!(None, matcher.find("ERROR: took 150ms, retry"));
+        assert_eq!(None, matcher.find("took 150ms"));
+    }
+
+    #[test]
+    fn replace_every_match() {
+        let re = Regex::new(r"(?P<key>\w+)=(\d+)").unwrap();
+        let matcher = PatternMatcher::regex(re, None);
+
+        assert_eq!(
+            "a: 1, bc: 23;",
+            matcher.replace_all("${key}: $2", "a=1, bc=23;")
+        );
+        assert_eq!(
+            "Trust me, rust",
+            PatternMatcher::literal("RUST", true, None).replace_all("rust", "Trust me, RuSt")
+        );
+    }
+}
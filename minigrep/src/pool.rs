@@ -0,0 +1,142 @@
+use std::{
+    sync::{mpsc, Arc, Mutex},
+    thread,
+};
+
+/// `Worker` struct and implementations
+struct Worker {
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl Worker {
+    fn new<T: Send + 'static>(
+        receiver: Arc<Mutex<mpsc::Receiver<Job<T>>>>,
+        results: mpsc::Sender<T>,
+    ) -> Worker {
+        let thread = thread::spawn(move || loop {
+            let message = receiver.lock().unwrap().recv();
+
+            match message {
+                Ok(job) => {
+                    // nobody is waiting for results anymore, so neither are more jobs
+                    if results.send(job()).is_err() {
+                        break;
+                    }
+                }
+                Err(_) => break,
+            }
+        });
+
+        Worker {
+            thread: Some(thread),
+        }
+    }
+}
+
+/// `Job` struct and implementations
+type Job<T> = Box<dyn FnOnce() -> T + Send + 'static>;
+
+/// `ThreadPool` struct and implementations
+///
+/// The `ThreadPool` of the `hello` server, extended so that every job returns a result, which is
+/// sent back over a channel as soon as the job is done.
+pub struct ThreadPool<T> {
+    workers: Vec<Worker>,
+    sender: Option<mpsc::Sender<Job<T>>>,
+    results: mpsc::Receiver<T>,
+}
+
+impl<T> Drop for ThreadPool<T> {
+    fn drop(&mut self) {
+        drop(self.sender.take());
+
+        for worker in &mut self.workers {
+            if let Some(thread) = worker.thread.take() {
+                thread.join().unwrap();
+            }
+        }
+    }
+}
+
+impl<T: Send + 'static> ThreadPool<T> {
+    /// Create a new `ThreadPool`.
+    ///
+    /// The size is the number of threads in the pool.
+    ///
+    /// # Panics
+    ///
+    /// The `new` function will panic if the size is zero.
+    pub fn new(size: usize) -> ThreadPool<T> {
+        assert!(size > 0);
+
+        let (sender, receiver) = mpsc::channel();
+        let (results_sender, results) = mpsc::channel();
+
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        let workers = (0..size)
+            .map(|_| Worker::new(Arc::clone(&receiver), results_sender.clone()))
+            .collect();
+
+        ThreadPool {
+            workers,
+            sender: Some(sender),
+            results,
+        }
+    }
+
+    pub fn execute<F>(&self, f: F)
+    where
+        F: FnOnce() -> T + Send + 'static,
+    {
+        let job = Box::new(f);
+
+        self.sender
+            .as_ref()
+            .unwrap()
+            .send(job)
+            .expect("the receiver shutdown");
+    }
+
+    /// Stop taking jobs and iterate over the results of the ones already given, in the order they
+    /// finish. The workers are joined once the iterator is dropped.
+    pub fn into_results(mut self) -> Results<T> {
+        drop(self.sender.take());
+        Results { pool: self }
+    }
+}
+
+/// `Results` struct and implementations
+///
+/// The iterator returned by `ThreadPool::into_results`.
+pub struct Results<T> {
+    pool: ThreadPool<T>,
+}
+
+impl<T> Iterator for Results<T> {
+    type Item = T;
+
+    /// Wait for the next job to finish; `None` once every worker has run out of jobs.
+    fn next(&mut self) -> Option<T> {
+        self.pool.results.recv().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_result_comes_back() {
+        let pool = ThreadPool::new(4);
+
+        for i in 0..100 {
+            pool.execute(move || i * 2);
+        }
+
+        let mut results: Vec<_> = pool.into_results().collect();
+        results.sort();
+
+        assert_eq!((0..100).map(|i| i * 2).collect::<Vec<_>>(), results);
+    }
+}
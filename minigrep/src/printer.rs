@@ -0,0 +1,662 @@
+use std::{
+    fmt::Display,
+    io::{self, IsTerminal, Write},
+    ops::Range,
+    time::Duration,
+};
+
+use serde_json::json;
+
+use crate::{
+    matcher::{Matcher, PatternMatcher},
+    Block, Config, Mode, SearchLine,
+};
+
+/// How results are written to stdout.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// grep-style `path:line:text` lines.
+    #[default]
+    Standard,
+    /// One JSON object per line: `begin`, `match`, `context` and `end` records for every file,
+    /// then a final `summary`.
+    Json,
+}
+
+/// When the standard printer colors its output, chosen with `--color`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ColorChoice {
+    Always,
+    Never,
+    /// Color only when stdout is a terminal.
+    #[default]
+    Auto,
+}
+
+impl ColorChoice {
+    pub fn enabled(self) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => io::stdout().is_terminal(),
+        }
+    }
+}
+
+// the ANSI SGR sequences grep uses by default
+const MATCH_COLOR: &str = "\x1b[1;31m";
+const PATH_COLOR: &str = "\x1b[35m";
+const NUMBER_COLOR: &str = "\x1b[32m";
+const SEPARATOR_COLOR: &str = "\x1b[36m";
+const RESET: &str = "\x1b[0m";
+
+/// Counts collected while searching, per file and in total.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// Number of inputs searched.
+    pub searches: usize,
+    /// Number of inputs with at least one matching line.
+    pub searches_with_match: usize,
+    pub matched_lines: usize,
+}
+
+impl Stats {
+    pub fn add(&mut self, other: &Stats) {
+        self.searches += other.searches;
+        self.searches_with_match += other.searches_with_match;
+        self.matched_lines += other.matched_lines;
+    }
+}
+
+/// `Printer` trait
+///
+/// `run` drives a printer with every file it searches, so adding an output format only means
+/// adding an implementation of this trait.
+pub trait Printer {
+    /// Called before anything is searched in `path`.
+    fn begin(&mut self, path: &str) -> io::Result<()>;
+
+    /// Called for every line `search_reader` hands out for `path`.
+    fn line(&mut self, path: &str, line: &SearchLine<'_>) -> io::Result<()>;
+
+    /// Called once `path` has been searched to the end.
+    fn end(&mut self, path: &str, stats: &Stats) -> io::Result<()>;
+
+    /// Called with what a printer like this one wrote for a file searched on another thread,
+    /// along with that file's stats, to be written out as if this printer had written it.
+    fn append(&mut self, output: &[u8], stats: &Stats) -> io::Result<()>;
+
+    /// Called once after every file has been searched.
+    fn summary(&mut self, stats: &Stats, elapsed: Duration) -> io::Result<()>;
+}
+
+/// `StandardPrinter` struct and implementations
+pub struct StandardPrinter<'a, W: Write> {
+    out: W,
+    config: &'a Config,
+    matcher: &'a PatternMatcher,
+    /// Prefix lines with their path, like `grep -r`.
+    with_path: bool,
+    color: bool,
+    printed_any: bool,
+    first_in_file: bool,
+}
+
+impl<'a, W: Write> StandardPrinter<'a, W> {
+    pub fn new(
+        out: W,
+        config: &'a Config,
+        matcher: &'a PatternMatcher,
+        with_path: bool,
+        color: bool,
+    ) -> Self {
+        StandardPrinter {
+            out,
+            config,
+            matcher,
+            with_path,
+            color,
+            printed_any: false,
+            first_in_file: true,
+        }
+    }
+
+    /// Write the `path:line:offset:` prefix; grep separates fields with `:` on matches and `-` on
+    /// context.
+    ///
+    /// A fuzzy match's edit distance comes last, as `~distance`.
+    fn write_prefix(
+        &mut self,
+        path: &str,
+        separator: char,
+        line_number: usize,
+        byte_offset: usize,
+        distance: Option<usize>,
+    ) -> io::Result<()> {
+        if self.with_path {
+            self.paint(PATH_COLOR, path)?;
+            self.paint(SEPARATOR_COLOR, separator)?;
+        }
+        if self.config.line_number {
+            self.paint(NUMBER_COLOR, line_number)?;
+            self.paint(SEPARATOR_COLOR, separator)?;
+        }
+        if self.config.byte_offset {
+            self.paint(NUMBER_COLOR, byte_offset)?;
+            self.paint(SEPARATOR_COLOR, separator)?;
+        }
+        if let Some(distance) = distance {
+            self.paint(NUMBER_COLOR, format!("~{distance}"))?;
+            self.paint(SEPARATOR_COLOR, separator)?;
+        }
+
+        Ok(())
+    }
+
+    fn paint(&mut self, color: &str, text: impl Display) -> io::Result<()> {
+        if self.color {
+            write!(self.out, "{color}{text}{RESET}")
+        } else {
+            write!(self.out, "{text}")
+        }
+    }
+
+    /// Write `line` with every match highlighted, or with `replace`, every replacement.
+    fn write_highlighted(&mut self, line: &str, replace: Option<&str>) -> io::Result<()> {
+        let mut written = 0;
+
+        for range in self.matcher.find_iter(line) {
+            write!(self.out, "{}", &line[written..range.start])?;
+
+            match replace {
+                Some(template) => {
+                    let replacement = self.matcher.expand_match(template, line, range.clone());
+                    self.paint(MATCH_COLOR, replacement)?;
+                }
+                // an empty match has nothing to highlight
+                None if range.is_empty() => {}
+                None => self.paint(MATCH_COLOR, &line[range.clone()])?,
+            }
+            written = range.end;
+        }
+
+        writeln!(self.out, "{}", &line[written..])
+    }
+
+    /// Write every line of `block` with a prefix of its own, highlighting the part of each match
+    /// that is on it.
+    fn write_block(&mut self, path: &str, block: &Block<'_>) -> io::Result<()> {
+        if let Some(template) = &self.config.format {
+            self.write_prefix(path, ':', block.line_number, block.byte_offset, None)?;
+            let text = self.matcher.expand(template, block.lines);
+            return writeln!(self.out, "{text}");
+        }
+
+        let mut start = 0;
+
+        for (i, raw) in block.lines.split('\n').enumerate() {
+            let line = raw.strip_suffix('\r').unwrap_or(raw);
+            let line_number = block.line_number + i;
+            self.write_prefix(path, ':', line_number, block.byte_offset + start, None)?;
+
+            let mut written = 0;
+            if self.color {
+                for (_, range) in &block.matches {
+                    let from = range.start.clamp(start, start + line.len()) - start;
+                    let to = range.end.clamp(start, start + line.len()) - start;
+                    if from == to {
+                        continue;
+                    }
+
+                    write!(self.out, "{}", &line[written..from])?;
+                    self.paint(MATCH_COLOR, &line[from..to])?;
+                    written = to;
+                }
+            }
+            writeln!(self.out, "{}", &line[written..])?;
+
+            start += raw.len() + 1;
+        }
+
+        Ok(())
+    }
+}
+
+impl<W: Write> Printer for StandardPrinter<'_, W> {
+    fn begin(&mut self, _path: &str) -> io::Result<()> {
+        self.first_in_file = true;
+        Ok(())
+    }
+
+    fn line(&mut self, path: &str, line: &SearchLine<'_>) -> io::Result<()> {
+        // groups in different files are separated just like groups within a file
+        if self.first_in_file && self.printed_any && !self.config.context.is_empty() {
+            self.paint(SEPARATOR_COLOR, "--")?;
+            writeln!(self.out)?;
+        }
+        self.first_in_file = false;
+        self.printed_any = true;
+
+        match line {
+            SearchLine::Match(m) => {
+                let distance = if self.matcher.is_fuzzy() {
+                    self.matcher
+                        .find_distance(m.line)
+                        .map(|(distance, _)| distance)
+                } else {
+                    None
+                };
+                self.write_prefix(path, ':', m.line_number, m.byte_offset, distance)?;
+
+                match &self.config.format {
+                    Some(template) => {
+                        let text = self.matcher.expand(template, m.line);
+                        writeln!(self.out, "{text}")
+                    }
+                    None if self.color => {
+                        self.write_highlighted(m.line, self.config.replace.as_deref())
+                    }
+                    None => match &self.config.replace {
+                        Some(template) => {
+                            let text = self.matcher.replace_all(template, m.line);
+                            writeln!(self.out, "{text}")
+                        }
+                        None => writeln!(self.out, "{}", m.line),
+                    },
+                }
+            }
+            SearchLine::Context {
+                line_number,
+                byte_offset,
+                line,
+            } => {
+                self.write_prefix(path, '-', *line_number, *byte_offset, None)?;
+                writeln!(self.out, "{line}")
+            }
+            SearchLine::Break => {
+                self.paint(SEPARATOR_COLOR, "--")?;
+                writeln!(self.out)
+            }
+            // named even when searching one file, since the line itself isn't printed
+            SearchLine::Binary { .. } => {
+                self.paint(PATH_COLOR, path)?;
+                writeln!(self.out, ": binary file matches")
+            }
+            SearchLine::Block(block) => self.write_block(path, block),
+        }
+    }
+
+    fn end(&mut self, path: &str, stats: &Stats) -> io::Result<()> {
+        let listed = match self.config.mode {
+            Mode::Lines => return Ok(()),
+            Mode::Count => {
+                if self.with_path {
+                    self.paint(PATH_COLOR, path)?;
+                    self.paint(SEPARATOR_COLOR, ':')?;
+                }
+                return writeln!(self.out, "{}", stats.matched_lines);
+            }
+            Mode::FilesWithMatches => stats.matched_lines > 0,
+            Mode::FilesWithoutMatch => stats.matched_lines == 0,
+        };
+
+        if listed {
+            self.paint(PATH_COLOR, path)?;
+            writeln!(self.out)?;
+        }
+
+        Ok(())
+    }
+
+    fn append(&mut self, output: &[u8], stats: &Stats) -> io::Result<()> {
+        let printed_lines = self.config.mode == Mode::Lines && stats.matched_lines > 0;
+
+        // the printer that wrote it didn't know about the groups of lines before its own
+        if printed_lines && self.printed_any && !self.config.context.is_empty() {
+            self.paint(SEPARATOR_COLOR, "--")?;
+            writeln!(self.out)?;
+        }
+        self.printed_any |= printed_lines;
+
+        self.out.write_all(output)
+    }
+
+    fn summary(&mut self, _stats: &Stats, _elapsed: Duration) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// `JsonPrinter` struct and implementations
+///
+/// Writes JSON Lines for editors and scripts. Offsets and columns count bytes; `column` is 1-based
+/// like `line_number`, while submatch `start` and `end` are 0-based offsets into `line`. A match
+/// spanning lines has `lines` instead of `line`, and an `end_line_number`.
+pub struct JsonPrinter<'a, W: Write> {
+    out: W,
+    matcher: &'a PatternMatcher,
+}
+
+impl<'a, W: Write> JsonPrinter<'a, W> {
+    pub fn new(out: W, matcher: &'a PatternMatcher) -> Self {
+        JsonPrinter { out, matcher }
+    }
+
+    fn submatch(&self, text: &str, pattern: usize, range: Range<usize>) -> serde_json::Value {
+        json!({
+            "match": &text[range.clone()],
+            "pattern": &self.matcher.patterns()[pattern],
+            "start": range.start,
+            "end": range.end,
+        })
+    }
+
+    fn write(&mut self, record: serde_json::Value) -> io::Result<()> {
+        serde_json::to_writer(&mut self.out, &record)?;
+        writeln!(self.out)
+    }
+}
+
+fn stats_json(stats: &Stats) -> serde_json::Value {
+    json!({
+        "searches": stats.searches,
+        "searches_with_match": stats.searches_with_match,
+        "matched_lines": stats.matched_lines,
+    })
+}
+
+impl<W: Write> Printer for JsonPrinter<'_, W> {
+    fn begin(&mut self, path: &str) -> io::Result<()> {
+        self.write(json!({ "type": "begin", "path": path }))
+    }
+
+    fn line(&mut self, path: &str, line: &SearchLine<'_>) -> io::Result<()> {
+        let record = match line {
+            SearchLine::Match(m) => {
+                let submatches: Vec<_> = self
+                    .matcher
+                    .find_patterns(m.line)
+                    .into_iter()
+                    .map(|(pattern, range)| self.submatch(m.line, pattern, range))
+                    .collect();
+
+                let mut record = json!({
+                    "type": "match",
+                    "path": path,
+                    "line_number": m.line_number,
+                    "byte_offset": m.byte_offset,
+                    "column": m.byte_range.start + 1,
+                    "line": m.line,
+                    "match": &m.line[m.byte_range.clone()],
+                    "submatches": submatches,
+                });
+
+                if self.matcher.is_fuzzy() {
+                    if let Some((distance, _)) = self.matcher.find_distance(m.line) {
+                        record["distance"] = json!(distance);
+                    }
+                }
+
+                record
+            }
+            SearchLine::Context {
+                line_number,
+                byte_offset,
+                line,
+            } => json!({
+                "type": "context",
+                "path": path,
+                "line_number": line_number,
+                "byte_offset": byte_offset,
+                "line": line,
+            }),
+            // consumers can tell groups apart from the line numbers
+            SearchLine::Break => return Ok(()),
+            SearchLine::Binary {
+                line_number,
+                byte_offset,
+            } => json!({
+                "type": "binary",
+                "path": path,
+                "line_number": line_number,
+                "byte_offset": byte_offset,
+            }),
+            SearchLine::Block(block) => {
+                let submatches: Vec<_> = block
+                    .matches
+                    .iter()
+                    .map(|(pattern, range)| self.submatch(block.lines, *pattern, range.clone()))
+                    .collect();
+                let first = block
+                    .matches
+                    .first()
+                    .map_or(0..0, |(_, range)| range.clone());
+
+                json!({
+                    "type": "match",
+                    "path": path,
+                    "line_number": block.line_number,
+                    "end_line_number": block.end_line_number,
+                    "byte_offset": block.byte_offset,
+                    "column": first.start + 1,
+                    "lines": block.lines,
+                    "match": &block.lines[first],
+                    "submatches": submatches,
+                })
+            }
+        };
+
+        self.write(record)
+    }
+
+    fn end(&mut self, path: &str, stats: &Stats) -> io::Result<()> {
+        self.write(json!({ "type": "end", "path": path, "stats": stats_json(stats) }))
+    }
+
+    fn append(&mut self, output: &[u8], _stats: &Stats) -> io::Result<()> {
+        self.out.write_all(output)
+    }
+
+    fn summary(&mut self, stats: &Stats, elapsed: Duration) -> io::Result<()> {
+        self.write(json!({
+            "type": "summary",
+            "elapsed_secs": elapsed.as_secs_f64(),
+            "stats": stats_json(stats),
+        }))?;
+        self.out.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Context, Match};
+    use serde_json::Value;
+
+    fn nobody() -> SearchLine<'static> {
+        SearchLine::Match(Match {
+            line_number: 2,
+            byte_offset: 25,
+            byte_range: 8..14,
+            line: "Are you nobody, nobody?",
+        })
+    }
+
+    #[test]
+    fn standard_prefixes() {
+        let config = Config {
+            line_number: true,
+            byte_offset: true,
+            ..Config::default()
+        };
+        let matcher = PatternMatcher::literal("nobody", false, None);
+        let mut out = Vec::new();
+
+        let mut printer = StandardPrinter::new(&mut out, &config, &matcher, true, false);
+        printer.begin("poem.txt").unwrap();
+        printer.line("poem.txt", &nobody()).unwrap();
+
+        assert_eq!(
+            "poem.txt:2:25:Are you nobody, nobody?\n",
+            String::from_utf8(out).unwrap()
+        );
+    }
+
+    #[test]
+    fn highlights_every_match() {
+        let config = Config {
+            line_number: true,
+            ..Config::default()
+        };
+        let matcher = PatternMatcher::literal("nobody", false, None);
+        let mut out = Vec::new();
+
+        let mut printer = StandardPrinter::new(&mut out, &config, &matcher, true, true);
+        printer.line("poem.txt", &nobody()).unwrap();
+
+        assert_eq!(
+            "\x1b[35mpoem.txt\x1b[0m\x1b[36m:\x1b[0m\x1b[32m2\x1b[0m\x1b[36m:\x1b[0m\
+             Are you \x1b[1;31mnobody\x1b[0m, \x1b[1;31mnobody\x1b[0m?\n",
+            String::from_utf8(out).unwrap()
+        );
+    }
+
+    #[test]
+    fn prints_replacements() {
+        let config = Config {
+            replace: Some(String::from("somebody")),
+            ..Config::default()
+        };
+        let matcher = PatternMatcher::literal("nobody", false, None);
+        let mut out = Vec::new();
+
+        let mut printer = StandardPrinter::new(&mut out, &config, &matcher, false, false);
+        printer.line("poem.txt", &nobody()).unwrap();
+
+        assert_eq!(
+            "Are you somebody, somebody?\n",
+            String::from_utf8(out).unwrap()
+        );
+    }
+
+    #[test]
+    fn fuzzy_distances() {
+        let matcher = PatternMatcher::fuzzy("nobdy", 1, false, None);
+        let mut out = Vec::new();
+
+        let config = Config::default();
+        let mut printer = StandardPrinter::new(&mut out, &config, &matcher, true, false);
+        printer.line("poem.txt", &nobody()).unwrap();
+
+        let mut json = Vec::new();
+        JsonPrinter::new(&mut json, &matcher)
+            .line("poem.txt", &nobody())
+            .unwrap();
+        let record: Value = serde_json::from_slice(&json).unwrap();
+
+        assert_eq!(
+            "poem.txt:~1:Are you nobody, nobody?\n",
+            String::from_utf8(out).unwrap()
+        );
+        assert_eq!(1, record["distance"]);
+    }
+
+    #[test]
+    fn appended_groups_are_separated() {
+        let config = Config {
+            context: Context {
+                before: 1,
+                after: 0,
+            },
+            ..Config::default()
+        };
+        let matcher = PatternMatcher::literal("nobody", false, None);
+        let matched = Stats {
+            searches: 1,
+            searches_with_match: 1,
+            matched_lines: 1,
+        };
+
+        let mut out = Vec::new();
+        let mut printer = StandardPrinter::new(&mut out, &config, &matcher, true, false);
+        printer.append(b"", &Stats::default()).unwrap();
+        printer.append(b"a.txt:nobody\n", &matched).unwrap();
+        printer.append(b"b.txt:nobody\n", &matched).unwrap();
+
+        assert_eq!(
+            "a.txt:nobody\n--\nb.txt:nobody\n",
+            String::from_utf8(out).unwrap()
+        );
+    }
+
+    #[test]
+    fn counts_and_file_lists() {
+        let matcher = PatternMatcher::literal("nobody", false, None);
+        let matched = Stats {
+            searches: 1,
+            searches_with_match: 1,
+            matched_lines: 2,
+        };
+        let unmatched = Stats {
+            searches: 1,
+            ..Stats::default()
+        };
+
+        let render = |mode: Mode| {
+            let config = Config {
+                mode,
+                ..Config::default()
+            };
+            let mut out = Vec::new();
+            let mut printer = StandardPrinter::new(&mut out, &config, &matcher, true, false);
+            printer.end("a.txt", &matched).unwrap();
+            printer.end("b.txt", &unmatched).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        assert_eq!("a.txt:2\nb.txt:0\n", render(Mode::Count));
+        assert_eq!("a.txt\n", render(Mode::FilesWithMatches));
+        assert_eq!("b.txt\n", render(Mode::FilesWithoutMatch));
+        assert_eq!("", render(Mode::Lines));
+    }
+
+    #[test]
+    fn json_lines() {
+        let matcher = PatternMatcher::literal("nobody", false, None);
+        let mut out = Vec::new();
+
+        let mut printer = JsonPrinter::new(&mut out, &matcher);
+        printer.begin("poem.txt").unwrap();
+        printer.line("poem.txt", &nobody()).unwrap();
+        printer.line("poem.txt", &SearchLine::Break).unwrap();
+        let stats = Stats {
+            searches: 1,
+            searches_with_match: 1,
+            matched_lines: 1,
+        };
+        printer.end("poem.txt", &stats).unwrap();
+        printer.summary(&stats, Duration::from_millis(5)).unwrap();
+
+        let records: Vec<Value> = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let types: Vec<_> = records
+            .iter()
+            .map(|r| r["type"].as_str().unwrap())
+            .collect();
+
+        assert_eq!(vec!["begin", "match", "end", "summary"], types);
+        assert_eq!(2, records[1]["line_number"]);
+        assert_eq!(9, records[1]["column"]);
+        assert_eq!("nobody", records[1]["match"]);
+        assert_eq!(
+            json!([
+                { "match": "nobody", "pattern": "nobody", "start": 8, "end": 14 },
+                { "match": "nobody", "pattern": "nobody", "start": 16, "end": 22 },
+            ]),
+            records[1]["submatches"]
+        );
+        assert_eq!(1, records[2]["stats"]["matched_lines"]);
+    }
+}
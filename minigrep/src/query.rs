@@ -0,0 +1,342 @@
+use std::{error::Error, fmt};
+
+/// `Expr` enum and implementations
+///
+/// A boolean query such as `timeout AND (db OR cache) NOT retry`, parsed by `parse`. Each term
+/// is a `T`: the text of a word or quoted phrase at first, anything `map` turns it into later.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr<T = String> {
+    Term(T),
+    Not(Box<Expr<T>>),
+    And(Box<Expr<T>>, Box<Expr<T>>),
+    Or(Box<Expr<T>>, Box<Expr<T>>),
+}
+
+impl<T> Expr<T> {
+    /// The same expression with every term passed through `f`, from left to right.
+    pub fn map<U, E>(self, f: &mut impl FnMut(T) -> Result<U, E>) -> Result<Expr<U>, E> {
+        Ok(match self {
+            Expr::Term(term) => Expr::Term(f(term)?),
+            Expr::Not(expr) => Expr::Not(Box::new(expr.map(f)?)),
+            Expr::And(left, right) => Expr::And(Box::new(left.map(f)?), Box::new(right.map(f)?)),
+            Expr::Or(left, right) => Expr::Or(Box::new(left.map(f)?), Box::new(right.map(f)?)),
+        })
+    }
+
+    /// Whether the expression holds when each term holds as `is_match` says; terms the result
+    /// doesn't depend on are skipped.
+    pub fn eval(&self, is_match: &impl Fn(&T) -> bool) -> bool {
+        match self {
+            Expr::Term(term) => is_match(term),
+            Expr::Not(expr) => !expr.eval(is_match),
+            Expr::And(left, right) => left.eval(is_match) && right.eval(is_match),
+            Expr::Or(left, right) => left.eval(is_match) || right.eval(is_match),
+        }
+    }
+
+    /// Every term, from left to right.
+    pub fn terms(&self) -> Vec<&T> {
+        let mut terms = Vec::new();
+        self.collect(false, &mut terms, true);
+        terms
+    }
+
+    /// The terms that aren't negated, which are the ones worth highlighting in a selected line.
+    pub fn positive_terms(&self) -> Vec<&T> {
+        let mut terms = Vec::new();
+        self.collect(false, &mut terms, false);
+        terms
+    }
+
+    fn collect<'a>(&'a self, negated: bool, terms: &mut Vec<&'a T>, negated_too: bool) {
+        match self {
+            Expr::Term(term) if negated_too || !negated => terms.push(term),
+            Expr::Term(_) => {}
+            Expr::Not(expr) => expr.collect(!negated, terms, negated_too),
+            Expr::And(left, right) | Expr::Or(left, right) => {
+                left.collect(negated, terms, negated_too);
+                right.collect(negated, terms, negated_too);
+            }
+        }
+    }
+}
+
+/// `ParseError` struct and implementations
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// 1-based column, counted in characters, of where the problem is.
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at column {}", self.message, self.column)
+    }
+}
+
+impl Error for ParseError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Word(String),
+    Phrase(String),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+impl Token {
+    fn describe(&self) -> String {
+        match self {
+            Token::Word(word) => format!("'{word}'"),
+            Token::Phrase(phrase) => format!("\"{phrase}\""),
+            Token::And => String::from("AND"),
+            Token::Or => String::from("OR"),
+            Token::Not => String::from("NOT"),
+            Token::Open => String::from("'('"),
+            Token::Close => String::from("')'"),
+        }
+    }
+}
+
+/// Parse a query expression.
+///
+/// Terms are words or phrases in double quotes, where `\"` and `\\` escape a quote and a
+/// backslash. `NOT` binds tightest, then `AND`, then `OR`, and parentheses group. Terms next to
+/// each other without an operator are combined with `AND`. The operators are only recognized in
+/// uppercase; quote them to search for the words themselves.
+pub fn parse(input: &str) -> Result<Expr, ParseError> {
+    let mut parser = Parser {
+        tokens: tokenize(input)?,
+        position: 0,
+        end: input.chars().count() + 1,
+    };
+
+    let expr = parser.or()?;
+
+    match parser.peek() {
+        None => Ok(expr),
+        Some((column, Token::Close)) => Err(ParseError {
+            column,
+            message: String::from("unmatched ')'"),
+        }),
+        // `or` only returns early at a `)` or the end
+        Some((column, token)) => Err(ParseError {
+            column,
+            message: format!("unexpected {}", token.describe()),
+        }),
+    }
+}
+
+/// Split `input` into tokens, each with its 1-based column.
+fn tokenize(input: &str) -> Result<Vec<(usize, Token)>, ParseError> {
+    let mut tokens = Vec::new();
+    let mut chars = input.chars().zip(1..).peekable();
+
+    while let Some((c, column)) = chars.next() {
+        let token = match c {
+            _ if c.is_whitespace() => continue,
+            '(' => Token::Open,
+            ')' => Token::Close,
+            '"' => {
+                let mut phrase = String::new();
+
+                loop {
+                    match chars.next() {
+                        Some(('"', _)) => break,
+                        Some(('\\', _)) if matches!(chars.peek(), Some(('"' | '\\', _))) => {
+                            phrase.extend(chars.next().map(|(c, _)| c));
+                        }
+                        Some((c, _)) => phrase.push(c),
+                        None => {
+                            return Err(ParseError {
+                                column,
+                                message: String::from("unterminated phrase"),
+                            })
+                        }
+                    }
+                }
+
+                Token::Phrase(phrase)
+            }
+            _ => {
+                let mut word = String::from(c);
+
+                while let Some(&(c, _)) = chars.peek() {
+                    if c.is_whitespace() || matches!(c, '(' | ')' | '"') {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+
+                match word.as_str() {
+                    "AND" => Token::And,
+                    "OR" => Token::Or,
+                    "NOT" => Token::Not,
+                    _ => Token::Word(word),
+                }
+            }
+        };
+
+        tokens.push((column, token));
+    }
+
+    Ok(tokens)
+}
+
+/// A recursive descent parser, one method per level of precedence.
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    position: usize,
+    /// Column just past the input, where a missing term is reported.
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<(usize, &Token)> {
+        self.tokens
+            .get(self.position)
+            .map(|(column, token)| (*column, token))
+    }
+
+    fn next(&mut self) -> Option<(usize, Token)> {
+        let next = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        next
+    }
+
+    /// `and (OR and)*`
+    fn or(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.and()?;
+
+        while let Some((_, Token::Or)) = self.peek() {
+            self.next();
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+
+        Ok(expr)
+    }
+
+    /// `not (AND? not)*`
+    fn and(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.not()?;
+
+        loop {
+            match self.peek() {
+                Some((_, Token::And)) => {
+                    self.next();
+                }
+                // anything that can start a term continues the conjunction
+                Some((_, Token::Word(_) | Token::Phrase(_) | Token::Not | Token::Open)) => {}
+                _ => return Ok(expr),
+            }
+
+            expr = Expr::And(Box::new(expr), Box::new(self.not()?));
+        }
+    }
+
+    /// `NOT* term`
+    fn not(&mut self) -> Result<Expr, ParseError> {
+        if let Some((_, Token::Not)) = self.peek() {
+            self.next();
+            return Ok(Expr::Not(Box::new(self.not()?)));
+        }
+
+        self.term()
+    }
+
+    /// `word | phrase | ( or )`
+    fn term(&mut self) -> Result<Expr, ParseError> {
+        match self.next() {
+            Some((_, Token::Word(term) | Token::Phrase(term))) => Ok(Expr::Term(term)),
+            Some((column, Token::Open)) => {
+                let expr = self.or()?;
+
+                match self.next() {
+                    Some((_, Token::Close)) => Ok(expr),
+                    _ => Err(ParseError {
+                        column,
+                        message: String::from("unclosed '('"),
+                    }),
+                }
+            }
+            Some((column, token)) => Err(ParseError {
+                column,
+                message: format!("expected a term, found {}", token.describe()),
+            }),
+            None => Err(ParseError {
+                column: self.end,
+                message: String::from("expected a term"),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn term(term: &str) -> Box<Expr> {
+        Box::new(Expr::Term(term.to_string()))
+    }
+
+    #[test]
+    fn precedence_and_implicit_and() {
+        assert_eq!(
+            Ok(Expr::And(
+                Box::new(Expr::And(
+                    term("timeout"),
+                    Box::new(Expr::Or(term("db"), term("cache")))
+                )),
+                Box::new(Expr::Not(term("retry")))
+            )),
+            parse("timeout AND (db OR cache) NOT retry")
+        );
+        assert_eq!(
+            Ok(Expr::Or(
+                Box::new(Expr::And(term("a"), term("b"))),
+                Box::new(Expr::And(Box::new(Expr::Not(term("c"))), term("d")))
+            )),
+            parse("a b OR NOT c AND d")
+        );
+    }
+
+    #[test]
+    fn quoted_phrases() {
+        let expr = parse(r#""connection reset" OR "say \"AND\"""#).unwrap();
+
+        assert_eq!(vec!["connection reset", "say \"AND\""], expr.terms());
+    }
+
+    #[test]
+    fn errors_point_at_the_column() {
+        let error = |input| parse(input).unwrap_err();
+
+        assert_eq!(
+            ParseError {
+                column: 13,
+                message: String::from("unclosed '('"),
+            },
+            error("timeout AND (db OR cache")
+        );
+        assert_eq!(3, error("a ) b").column);
+        assert_eq!(4, error("db \"cache").column);
+        assert_eq!(8, error("db AND OR cache").column);
+        assert_eq!("expected a term at column 8", error("db AND ").to_string());
+    }
+
+    #[test]
+    fn evaluate_and_highlight() {
+        let expr = parse("timeout AND (db OR cache) NOT retry").unwrap();
+        let line = |words: &'static str| move |term: &String| words.contains(term.as_str());
+
+        assert!(expr.eval(&line("db timeout")));
+        assert!(!expr.eval(&line("db timeout, retry")));
+        assert!(!expr.eval(&line("cache")));
+        assert_eq!(vec!["timeout", "db", "cache"], expr.positive_terms());
+    }
+}
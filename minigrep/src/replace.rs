@@ -0,0 +1,151 @@
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use tempfile::NamedTempFile;
+
+use crate::matcher::{Matcher, PatternMatcher};
+
+/// `Rewrite` struct and implementations
+///
+/// A file's contents with the replacement applied to every matching line.
+pub struct Rewrite {
+    pub contents: String,
+    /// Line number, old line and new line of every line that changed.
+    pub changes: Vec<(usize, String, String)>,
+}
+
+impl Rewrite {
+    /// Replace the matches on every line of `contents`, keeping line terminators as they are.
+    pub fn new(matcher: &PatternMatcher, template: &str, contents: &str) -> Rewrite {
+        let mut rewritten = String::with_capacity(contents.len());
+        let mut changes = Vec::new();
+
+        for (i, raw) in contents.split_inclusive('\n').enumerate() {
+            let line = raw.trim_end_matches(['\n', '\r']);
+            let terminator = &raw[line.len()..];
+
+            if matcher.find(line).is_none() {
+                rewritten.push_str(raw);
+                continue;
+            }
+
+            let replaced = matcher.replace_all(template, line);
+            rewritten.push_str(&replaced);
+            rewritten.push_str(terminator);
+
+            if replaced != line {
+                changes.push((i + 1, line.to_string(), replaced));
+            }
+        }
+
+        Rewrite {
+            contents: rewritten,
+            changes,
+        }
+    }
+
+    /// Write the changes as a unified diff of `path`, one hunk per changed line.
+    pub fn write_diff(&self, path: &str, out: &mut impl Write) -> io::Result<()> {
+        if self.changes.is_empty() {
+            return Ok(());
+        }
+
+        writeln!(out, "--- {path}")?;
+        writeln!(out, "+++ {path}")?;
+
+        for (line_number, old, new) in &self.changes {
+            writeln!(out, "@@ -{line_number} +{line_number} @@")?;
+            writeln!(out, "-{old}")?;
+            writeln!(out, "+{new}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Read `path` for rewriting, refusing input that isn't UTF-8 text.
+pub fn read_text(path: &Path) -> io::Result<String> {
+    let bytes = fs::read(path)?;
+
+    if bytes.contains(&0) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "binary file, not rewritten",
+        ));
+    }
+
+    String::from_utf8(bytes)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "not valid UTF-8, not rewritten"))
+}
+
+/// Replace `path` with `contents` atomically: a reader sees either the old file or the new one.
+///
+/// With `backup_suffix`, the old file is first copied next to it with the suffix appended.
+pub fn write_atomically(
+    path: &Path,
+    contents: &str,
+    backup_suffix: Option<&str>,
+) -> io::Result<()> {
+    // the temporary file has to be on the same file system for the rename to be atomic
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+
+    let mut temp = NamedTempFile::new_in(dir)?;
+    temp.write_all(contents.as_bytes())?;
+    temp.as_file().sync_all()?;
+    fs::set_permissions(temp.path(), fs::metadata(path)?.permissions())?;
+
+    if let Some(suffix) = backup_suffix {
+        let mut backup = PathBuf::from(path).into_os_string();
+        backup.push(suffix);
+        fs::copy(path, backup)?;
+    }
+
+    temp.persist(path)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rewrite_keeps_line_endings() {
+        let matcher = PatternMatcher::literal("rust", false, None);
+        let rewrite = Rewrite::new(&matcher, "Rust", "rust\r\nsafe\ntrust");
+
+        assert_eq!("Rust\r\nsafe\ntRust", rewrite.contents);
+
+        let mut diff = Vec::new();
+        rewrite.write_diff("poem.txt", &mut diff).unwrap();
+        assert_eq!(
+            "--- poem.txt\n+++ poem.txt\n\
+             @@ -1 +1 @@\n-rust\n+Rust\n\
+             @@ -3 +3 @@\n-trust\n+tRust\n",
+            String::from_utf8(diff).unwrap()
+        );
+    }
+
+    #[test]
+    fn atomic_write_with_backup() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("poem.txt");
+        fs::write(&path, "old").unwrap();
+
+        write_atomically(&path, "new", Some(".bak")).unwrap();
+
+        assert_eq!("new", fs::read_to_string(&path).unwrap());
+        assert_eq!(
+            "old",
+            fs::read_to_string(dir.path().join("poem.txt.bak")).unwrap()
+        );
+        // nothing but the file and its backup is left behind
+        assert_eq!(2, fs::read_dir(dir.path()).unwrap().count());
+    }
+}
@@ -0,0 +1,242 @@
+use std::io::{self, BufRead};
+
+use crate::{
+    matcher::Matcher, numbered_lines, search_blocks, search_reader, Match, NumberedLines,
+    SearchLine, SearchOptions,
+};
+
+/// `Sink` trait
+///
+/// Receives what a `Searcher` finds, as it finds it. Only `matched` has to be implemented; a sink
+/// that also wants context lines, breaks, binary notices or multiline blocks overrides `line`.
+pub trait Sink {
+    /// Called for every selected line.
+    fn matched(&mut self, m: Match<'_>) -> io::Result<()>;
+
+    /// Called for everything a search sends. Hands selected lines to `matched` and drops the
+    /// rest.
+    fn line(&mut self, line: SearchLine<'_>) -> io::Result<()> {
+        match line {
+            SearchLine::Match(m) => self.matched(m),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl<F> Sink for F
+where
+    F: FnMut(Match<'_>) -> io::Result<()>,
+{
+    fn matched(&mut self, m: Match<'_>) -> io::Result<()> {
+        self(m)
+    }
+}
+
+/// `Searcher` struct and implementations
+///
+/// Runs any `Matcher` over input, line by line or with `multiline` as a whole, and hands what it
+/// selects to a `Sink` or out of an iterator.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Searcher {
+    pub options: SearchOptions,
+    /// Search the whole input at once, so matches can span lines, sending a `SearchLine::Block`
+    /// for each group of lines the matches touch.
+    pub multiline: bool,
+}
+
+impl Searcher {
+    pub fn new(options: SearchOptions) -> Searcher {
+        Searcher {
+            options,
+            multiline: false,
+        }
+    }
+
+    /// Search `reader`, sending every selected line to `sink` as soon as it is found.
+    pub fn search_reader<M, R, S>(&self, matcher: &M, reader: R, sink: &mut S) -> io::Result<()>
+    where
+        M: Matcher + ?Sized,
+        R: BufRead,
+        S: Sink + ?Sized,
+    {
+        if self.multiline {
+            return search_blocks(reader, matcher, self.options, |line| sink.line(line));
+        }
+
+        search_reader(
+            reader,
+            |line| matcher.find(line),
+            self.options,
+            |line| sink.line(line),
+        )
+    }
+
+    /// Search `contents` like `search_reader`.
+    pub fn search_str<M, S>(&self, matcher: &M, contents: &str, sink: &mut S) -> io::Result<()>
+    where
+        M: Matcher + ?Sized,
+        S: Sink + ?Sized,
+    {
+        self.search_reader(matcher, contents.as_bytes(), sink)
+    }
+
+    /// The lines of `contents` selected by `matcher`, one at a time.
+    ///
+    /// Only `invert` and `max_count` of the options apply: there is nowhere to send context lines
+    /// to, and the lines of `contents` are already text. `multiline` doesn't apply either.
+    pub fn iter<'m, 'c, M>(&self, matcher: &'m M, contents: &'c str) -> Matches<'m, 'c, M>
+    where
+        M: Matcher + ?Sized,
+    {
+        Matches {
+            matcher,
+            lines: numbered_lines(contents),
+            invert: self.options.invert,
+            remaining: self.options.max_count,
+        }
+    }
+}
+
+/// `Matches` struct and implementations
+///
+/// The iterator returned by `Searcher::iter`.
+pub struct Matches<'m, 'c, M: ?Sized> {
+    matcher: &'m M,
+    lines: NumberedLines<'c>,
+    invert: bool,
+    remaining: Option<usize>,
+}
+
+impl<'c, M: Matcher + ?Sized> Iterator for Matches<'_, 'c, M> {
+    type Item = Match<'c>;
+
+    fn next(&mut self) -> Option<Match<'c>> {
+        if self.remaining == Some(0) {
+            return None;
+        }
+
+        let m = self.lines.find_map(|(line_number, byte_offset, line)| {
+            let byte_range = match (self.matcher.find(line), self.invert) {
+                (Some(range), false) => range,
+                (None, true) => 0..0,
+                _ => return None,
+            };
+
+            Some(Match {
+                line_number,
+                byte_offset,
+                byte_range,
+                line,
+            })
+        })?;
+
+        if let Some(remaining) = &mut self.remaining {
+            *remaining -= 1;
+        }
+
+        Some(m)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fuzzy::Fuzzy;
+    use crate::matcher::{CaseInsensitive, Literal};
+    use crate::Context;
+    use regex::Regex;
+
+    const POEM: &str = "\
+I'm nobody! Who are you?
+Are you nobody, too?
+Then there's a pair of us - don't tell!
+They'd banish us, you know.";
+
+    #[test]
+    fn any_matcher_into_any_sink() {
+        let searcher = Searcher::default();
+        let matchers: Vec<Box<dyn Matcher>> = vec![
+            Box::new(Literal::new("you")),
+            Box::new(CaseInsensitive::new("YOU")),
+            Box::new(Regex::new(r"\byou\b").unwrap()),
+        ];
+
+        for matcher in &matchers {
+            let mut found = Vec::new();
+            searcher
+                .search_str(matcher.as_ref(), POEM, &mut |m: Match<'_>| {
+                    found.push((m.line_number, m.byte_range));
+                    Ok(())
+                })
+                .unwrap();
+
+            assert_eq!(vec![(1, 20..23), (2, 4..7), (4, 18..21)], found);
+        }
+    }
+
+    #[test]
+    fn sinks_can_ask_for_context() {
+        struct Lines(Vec<String>);
+
+        impl Sink for Lines {
+            fn matched(&mut self, m: Match<'_>) -> io::Result<()> {
+                self.0.push(format!("{}:{}", m.line_number, m.line));
+                Ok(())
+            }
+
+            fn line(&mut self, line: SearchLine<'_>) -> io::Result<()> {
+                match line {
+                    SearchLine::Context {
+                        line_number, line, ..
+                    } => self.0.push(format!("{line_number}-{line}")),
+                    SearchLine::Match(m) => self.matched(m)?,
+                    _ => {}
+                }
+                Ok(())
+            }
+        }
+
+        let searcher = Searcher::new(SearchOptions {
+            context: Context {
+                before: 1,
+                after: 0,
+            },
+            ..SearchOptions::default()
+        });
+        let mut lines = Lines(Vec::new());
+        searcher
+            .search_str(&Literal::new("banish"), POEM, &mut lines)
+            .unwrap();
+
+        assert_eq!(
+            vec![
+                "3-Then there's a pair of us - don't tell!",
+                "4:They'd banish us, you know."
+            ],
+            lines.0
+        );
+    }
+
+    #[test]
+    fn iterate_over_matches() {
+        let searcher = Searcher::new(SearchOptions {
+            invert: true,
+            max_count: Some(1),
+            ..SearchOptions::default()
+        });
+        let matches: Vec<_> = searcher
+            .iter(&Literal::new("nobody"), POEM)
+            .map(|m| m.line_number)
+            .collect();
+
+        assert_eq!(vec![3], matches);
+
+        // fuzzy matchers plug in the same way
+        let fuzzy = Fuzzy::new("nobdy", 1);
+        let matches: Vec<_> = Searcher::default()
+            .iter(&fuzzy, POEM)
+            .map(|m| &m.line[m.byte_range])
+            .collect();
+        assert_eq!(vec!["nobody", "nobody"], matches);
+    }
+}
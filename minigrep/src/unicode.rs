@@ -0,0 +1,179 @@
+use std::ops::Range;
+
+use caseless::Caseless;
+use unicode_normalization::{char::canonical_combining_class, UnicodeNormalization};
+
+/// Unicode normalization applied to the query and to every line before matching, so text that
+/// looks the same matches even when it is encoded differently.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Normalization {
+    /// Canonical composition: `e` + U+0301 matches `é`.
+    Nfc,
+    /// Compatibility composition: additionally `ﬁ` matches `fi` and `²` matches `2`.
+    Nfkc,
+}
+
+/// Normalize and, with `fold`, case fold `text` the way lines are transformed before matching.
+///
+/// Case folding is Unicode's full folding, so `ß` and `SS` both become `ss`.
+pub fn transform(text: &str, fold: bool, normalization: Option<Normalization>) -> String {
+    let normalize = |text: String| match normalization {
+        Some(Normalization::Nfc) => text.nfc().collect(),
+        Some(Normalization::Nfkc) => text.nfkc().collect(),
+        None => text,
+    };
+
+    let normalized = normalize(text.to_string());
+
+    if !fold {
+        return normalized;
+    }
+
+    // folding can leave decomposed characters behind, e.g. `İ` folds to `i` + U+0307
+    normalize(normalized.chars().default_case_fold().collect())
+}
+
+/// `Mapped` struct and implementations
+///
+/// A line after `transform`, remembering which bytes of the original line each transformed byte
+/// came from, so matches found in the transformed text can be reported in the original line.
+pub struct Mapped {
+    pub text: String,
+    /// For every byte of `text`, the range of the original line it was produced from.
+    origins: Vec<Range<usize>>,
+    len: usize,
+}
+
+impl Mapped {
+    pub fn new(line: &str, fold: bool, normalization: Option<Normalization>) -> Mapped {
+        let mut text = String::with_capacity(line.len());
+        let mut origins = Vec::with_capacity(line.len());
+
+        for segment in segments(line, normalization.is_some()) {
+            text.push_str(&transform(&line[segment.clone()], fold, normalization));
+            origins.resize(text.len(), segment);
+        }
+
+        Mapped {
+            text,
+            origins,
+            len: line.len(),
+        }
+    }
+
+    /// Translate a range of `text` into the smallest range of the original line that covers it.
+    pub fn original(&self, range: Range<usize>) -> Range<usize> {
+        let start = match self.origins.get(range.start) {
+            Some(origin) => origin.start,
+            None => self.len,
+        };
+
+        if range.is_empty() {
+            return start..start;
+        }
+
+        start..self.origins[range.end - 1].end
+    }
+
+    /// Translate an offset of the original line into the first byte of `text` at or after it.
+    pub fn position(&self, original: usize) -> usize {
+        // the origins of consecutive bytes never go backwards
+        self.origins
+            .partition_point(|origin| origin.start < original)
+    }
+}
+
+/// Split `line` into the pieces that are transformed independently.
+///
+/// Folding works one character at a time. Normalization needs a character together with the
+/// combining marks that follow it, and Hangul jamo together with the syllable they compose into.
+fn segments(line: &str, normalizing: bool) -> impl Iterator<Item = Range<usize>> + '_ {
+    let mut starts = line
+        .char_indices()
+        .filter(move |&(_, c)| !normalizing || starts_segment(c))
+        .map(|(i, _)| i)
+        .peekable();
+
+    std::iter::from_fn(move || {
+        let start = starts.next()?;
+        let end = starts.peek().copied().unwrap_or(line.len());
+        Some(start..end)
+    })
+}
+
+fn starts_segment(c: char) -> bool {
+    let hangul_vowel_or_trailing =
+        ('\u{1161}'..='\u{1175}').contains(&c) || ('\u{11A8}'..='\u{11C2}').contains(&c);
+
+    canonical_combining_class(c) == 0 && !hangul_vowel_or_trailing
+}
+
+/// Find an ASCII, already-lowercased `query` in an ASCII `line` without allocating.
+pub fn find_ascii_case_insensitive(query: &str, line: &str) -> Option<Range<usize>> {
+    if query.is_empty() {
+        return Some(0..0);
+    }
+
+    line.as_bytes()
+        .windows(query.len())
+        .position(|window| window.eq_ignore_ascii_case(query.as_bytes()))
+        .map(|start| start..start + query.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find(query: &str, line: &str, normalization: Option<Normalization>) -> Option<Range<usize>> {
+        let query = transform(query, true, normalization);
+        let mapped = Mapped::new(line, true, normalization);
+        let start = mapped.text.find(&query)?;
+
+        Some(mapped.original(start..start + query.len()))
+    }
+
+    #[test]
+    fn full_case_folding() {
+        assert_eq!("strasse", transform("STRAßE", true, None));
+        assert_eq!(Some(0..7), find("strasse", "STRAßE", None));
+        assert_eq!(Some(2..4), find("SS", "Maße", None));
+        // all of `ß` is reported, even though the query only covers half of its folding
+        assert_eq!(Some(2..4), find("s", "Maße", None));
+    }
+
+    #[test]
+    fn turkish_dotted_capital_i() {
+        // `İ` folds to `i` followed by a combining dot, which the query has to include too
+        assert_eq!(Some(0..10), find("İSTANBUL", "i\u{307}stanbul", None));
+        assert_eq!(None, find("istanbul", "ıstanbul", None));
+    }
+
+    #[test]
+    fn precomposed_and_decomposed_accents() {
+        let decomposed = "cafe\u{301} au lait";
+
+        assert_eq!(None, find("café", decomposed, None));
+        assert_eq!(
+            Some(0..6),
+            find("café", decomposed, Some(Normalization::Nfc))
+        );
+        assert_eq!(
+            Some(0..5),
+            find("CAFE\u{301}", "café", Some(Normalization::Nfc))
+        );
+    }
+
+    #[test]
+    fn compatibility_normalization() {
+        assert_eq!(None, find("x2", "x² + 1", Some(Normalization::Nfc)));
+        assert_eq!(Some(0..3), find("x2", "x² + 1", Some(Normalization::Nfkc)));
+        // full case folding already expands ligatures, even without NFKC
+        assert_eq!(Some(4..11), find("office", "the ofﬁce", None));
+    }
+
+    #[test]
+    fn ascii_without_allocating() {
+        assert_eq!(Some(1..5), find_ascii_case_insensitive("rust", "TRUST me"));
+        assert_eq!(None, find_ascii_case_insensitive("rust", "rus"));
+    }
+}
@@ -0,0 +1,476 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use globset::{Glob, GlobBuilder, GlobSet, GlobSetBuilder};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+
+use crate::index::INDEX_FILE;
+
+/// `GlobFilter` struct and implementations
+///
+/// Globs select which walked files get searched. A glob starting with `!` excludes, every other
+/// glob includes. A glob without a `/` matches the file name at any depth, like `.gitignore` does.
+pub struct GlobFilter {
+    includes: GlobSet,
+    excludes: GlobSet,
+}
+
+impl GlobFilter {
+    pub fn new(globs: &[String]) -> Result<GlobFilter, globset::Error> {
+        let mut includes = GlobSetBuilder::new();
+        let mut excludes = GlobSetBuilder::new();
+
+        for glob in globs {
+            match glob.strip_prefix('!') {
+                Some(pattern) => excludes.add(compile(pattern)?),
+                None => includes.add(compile(glob)?),
+            };
+        }
+
+        Ok(GlobFilter {
+            includes: includes.build()?,
+            excludes: excludes.build()?,
+        })
+    }
+
+    /// Should a walked file at `relative` (to its search root) be searched?
+    pub fn is_match(&self, relative: &Path) -> bool {
+        if self.excludes.is_match(relative) {
+            return false;
+        }
+
+        self.includes.is_empty() || self.includes.is_match(relative)
+    }
+
+    /// Can the walk skip the directory at `relative` entirely?
+    pub fn excludes_dir(&self, relative: &Path) -> bool {
+        // `target/**` only matches paths below `target`, so probe with a child as well
+        self.excludes.is_match(relative) || self.excludes.is_match(relative.join("_"))
+    }
+}
+
+fn compile(pattern: &str) -> Result<Glob, globset::Error> {
+    let pattern = pattern.trim_end_matches('/');
+
+    let pattern = if pattern.contains('/') {
+        pattern.trim_start_matches('/').to_string()
+    } else {
+        format!("**/{pattern}")
+    };
+
+    GlobBuilder::new(&pattern).literal_separator(true).build()
+}
+
+/// `SearchFile` struct and implementations
+pub struct SearchFile {
+    pub path: PathBuf,
+    /// Named directly on the command line rather than found by walking a directory.
+    pub explicit: bool,
+}
+
+/// Which files and directories a walk skips, besides those excluded by globs.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WalkOptions {
+    /// Don't respect `.gitignore`, `.ignore` or `.git/info/exclude`.
+    pub no_ignore: bool,
+    /// Walk into hidden files and directories, whose names start with `.`.
+    pub hidden: bool,
+}
+
+/// `Ignores` struct and implementations
+///
+/// The ignore files that apply to the directory being walked, outermost first. Like git, a
+/// deeper ignore file overrides a shallower one, and `.ignore` overrides `.gitignore` next to it.
+struct Ignores {
+    matchers: Vec<Gitignore>,
+}
+
+impl Ignores {
+    /// The ignore files that apply above `root`: `.git/info/exclude` and the ignore files of the
+    /// directories between the repository's top level and `root`.
+    fn above(root: &Path) -> Ignores {
+        let mut ignores = Ignores {
+            matchers: Vec::new(),
+        };
+
+        // `root` itself is included: its own ignore files are added once the walk enters it
+        let ancestors: Vec<&Path> = root.ancestors().collect();
+        let Some(top) = ancestors.iter().position(|dir| dir.join(".git").exists()) else {
+            return ignores;
+        };
+
+        ignores.add(ancestors[top], &ancestors[top].join(".git/info/exclude"));
+        for dir in ancestors[1..=top].iter().rev() {
+            ignores.push_dir(dir);
+        }
+
+        ignores
+    }
+
+    /// Add the ignore files in `dir`, returning how many were found.
+    fn push_dir(&mut self, dir: &Path) -> usize {
+        let before = self.matchers.len();
+
+        self.add(dir, &dir.join(".gitignore"));
+        self.add(dir, &dir.join(".ignore"));
+
+        self.matchers.len() - before
+    }
+
+    fn add(&mut self, dir: &Path, file: &Path) {
+        if !file.is_file() {
+            return;
+        }
+
+        // like git, lines that aren't valid patterns are skipped rather than failing the search
+        let mut builder = GitignoreBuilder::new(dir);
+        builder.add(file);
+
+        if let Ok(matcher) = builder.build() {
+            self.matchers.push(matcher);
+        }
+    }
+
+    fn pop(&mut self, count: usize) {
+        self.matchers.truncate(self.matchers.len() - count);
+    }
+
+    /// Is the file or directory at the absolute `path` ignored?
+    fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        // the innermost ignore file with a matching pattern decides, so `!` can re-include
+        for matcher in self.matchers.iter().rev() {
+            match matcher.matched(path, is_dir) {
+                ignore::Match::None => continue,
+                ignore::Match::Ignore(_) => return true,
+                ignore::Match::Whitelist(_) => return false,
+            }
+        }
+
+        false
+    }
+}
+
+/// Expand `paths` into the files to search, walking directories recursively in sorted order.
+///
+/// Files named directly are always searched; `filter`, ignore files and hidden names only apply to
+/// what is found by walking. An index file found by walking is never searched, and links to
+/// directories aren't followed.
+///
+/// A directory that can't be read is reported and skipped, and the rest of the walk goes on.
+/// Returns the files and the number of directories skipped that way.
+pub fn collect_files(
+    paths: &[String],
+    filter: &GlobFilter,
+    options: WalkOptions,
+) -> io::Result<(Vec<SearchFile>, usize)> {
+    let mut files = Vec::new();
+    let mut unreadable = 0;
+
+    for path in paths {
+        let root = Path::new(path);
+
+        if root.is_dir() {
+            // ignore files match absolute paths, so track one next to the path that gets printed
+            let absolute = fs::canonicalize(root)?;
+            let mut ignores = if options.no_ignore {
+                Ignores {
+                    matchers: Vec::new(),
+                }
+            } else {
+                Ignores::above(&absolute)
+            };
+
+            let mut walk = Walk {
+                root,
+                filter,
+                options,
+                ignores: &mut ignores,
+                files: &mut files,
+                unreadable: 0,
+            };
+            walk.dir(root, &absolute);
+            unreadable += walk.unreadable;
+        } else {
+            files.push(SearchFile {
+                path: root.to_path_buf(),
+                explicit: true,
+            });
+        }
+    }
+
+    Ok((files, unreadable))
+}
+
+/// `Walk` struct and implementations
+struct Walk<'a> {
+    root: &'a Path,
+    filter: &'a GlobFilter,
+    options: WalkOptions,
+    ignores: &'a mut Ignores,
+    files: &'a mut Vec<SearchFile>,
+    /// Directories that couldn't be read, reported as they're met.
+    unreadable: usize,
+}
+
+impl Walk<'_> {
+    fn dir(&mut self, dir: &Path, absolute: &Path) {
+        let entries = fs::read_dir(dir).and_then(|entries| {
+            entries
+                .map(|entry| entry.and_then(|entry| Ok((entry.file_name(), entry.file_type()?))))
+                .collect::<io::Result<Vec<_>>>()
+        });
+        let mut entries = match entries {
+            Ok(entries) => entries,
+            Err(e) => {
+                eprintln!("minigrep: {}: {e}", dir.display());
+                self.unreadable += 1;
+                return;
+            }
+        };
+        entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        let pushed = if self.options.no_ignore {
+            0
+        } else {
+            self.ignores.push_dir(absolute)
+        };
+
+        for (name, file_type) in entries {
+            if !self.options.hidden && name.to_string_lossy().starts_with('.') {
+                continue;
+            }
+            // the index is never searched, not even along with the other hidden files
+            if name == INDEX_FILE {
+                continue;
+            }
+
+            let path = dir.join(&name);
+            let absolute = absolute.join(&name);
+            let relative = path.strip_prefix(self.root).unwrap_or(&path);
+            let is_dir = file_type.is_dir();
+
+            // following a link to a directory could walk in circles, as with `up -> ..`, so only
+            // links to files are searched
+            if file_type.is_symlink() && path.is_dir() {
+                continue;
+            }
+            if self.ignores.is_ignored(&absolute, is_dir) {
+                continue;
+            }
+
+            if is_dir {
+                if !self.filter.excludes_dir(relative) {
+                    self.dir(&path, &absolute);
+                }
+            } else if self.filter.is_match(relative) {
+                self.files.push(SearchFile {
+                    path,
+                    explicit: false,
+                });
+            }
+        }
+
+        self.ignores.pop(pushed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(globs: &[&str]) -> GlobFilter {
+        let globs: Vec<String> = globs.iter().map(|glob| glob.to_string()).collect();
+        GlobFilter::new(&globs).unwrap()
+    }
+
+    #[test]
+    fn include_and_exclude_globs() {
+        let filter = filter(&["*.rs", "!target/**"]);
+
+        assert!(filter.is_match(Path::new("main.rs")));
+        assert!(filter.is_match(Path::new("src/lib.rs")));
+        assert!(!filter.is_match(Path::new("Cargo.toml")));
+        assert!(!filter.is_match(Path::new("target/debug/build.rs")));
+        assert!(filter.excludes_dir(Path::new("target")));
+        assert!(!filter.excludes_dir(Path::new("src")));
+    }
+
+    #[test]
+    fn walks_directories_recursively() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("src/nested")).unwrap();
+        fs::create_dir_all(dir.path().join("target")).unwrap();
+        fs::write(dir.path().join("src/lib.rs"), "").unwrap();
+        fs::write(dir.path().join("src/nested/mod.rs"), "").unwrap();
+        fs::write(dir.path().join("target/out.rs"), "").unwrap();
+        fs::write(dir.path().join("notes.txt"), "").unwrap();
+
+        let root = dir.path().to_str().unwrap().to_string();
+        let files = collect_files(
+            &[root],
+            &filter(&["*.rs", "!target/**"]),
+            WalkOptions::default(),
+        )
+        .unwrap()
+        .0;
+        let relative: Vec<_> = files
+            .iter()
+            .map(|file| file.path.strip_prefix(dir.path()).unwrap().to_path_buf())
+            .collect();
+
+        assert_eq!(
+            vec![
+                PathBuf::from("src/lib.rs"),
+                PathBuf::from("src/nested/mod.rs")
+            ],
+            relative
+        );
+    }
+
+    fn walk(dir: &Path, options: WalkOptions) -> Vec<String> {
+        let root = dir.to_str().unwrap().to_string();
+        let (files, unreadable) = collect_files(&[root], &filter(&[]), options).unwrap();
+        assert_eq!(0, unreadable);
+
+        files
+            .iter()
+            .map(|file| {
+                let relative = file.path.strip_prefix(dir).unwrap();
+                relative.to_str().unwrap().replace('\\', "/")
+            })
+            .collect()
+    }
+
+    #[test]
+    fn respects_ignore_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let write = |path: &str, contents: &str| {
+            let path = dir.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        };
+
+        write(".git/info/exclude", "*.tmp\n");
+        write(".gitignore", "*.log\n!keep.log\n/build\ncache/\n");
+        write(".ignore", "secret.txt\n");
+        write("build/out.txt", "");
+        write("src/build/gen.txt", "");
+        write("src/cache", "");
+        write("src/lib.rs", "");
+        write("src/.gitignore", "*.rs\n!lib.rs\n");
+        write("src/main.rs", "");
+        write("debug.log", "");
+        write("keep.log", "");
+        write("secret.txt", "");
+        write("scratch.tmp", "");
+        write("vendor/cache/dep.rs", "");
+
+        assert_eq!(
+            vec![
+                "keep.log",
+                // `/build` is anchored to the top level, and `cache/` only matches directories
+                "src/build/gen.txt",
+                "src/cache",
+                "src/lib.rs",
+            ],
+            walk(dir.path(), WalkOptions::default())
+        );
+
+        let everything = WalkOptions {
+            no_ignore: true,
+            hidden: true,
+        };
+        assert_eq!(14, walk(dir.path(), everything).len());
+    }
+
+    #[test]
+    fn ignore_files_above_the_search_root() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join(".git")).unwrap();
+        fs::create_dir_all(dir.path().join("src/target")).unwrap();
+        fs::write(dir.path().join(".gitignore"), "target/\n").unwrap();
+        fs::write(dir.path().join("src/.hidden.rs"), "").unwrap();
+        fs::write(dir.path().join("src/lib.rs"), "").unwrap();
+        fs::write(dir.path().join("src/target/out.rs"), "").unwrap();
+
+        assert_eq!(
+            vec!["lib.rs"],
+            walk(&dir.path().join("src"), WalkOptions::default())
+        );
+
+        let hidden = WalkOptions {
+            hidden: true,
+            ..WalkOptions::default()
+        };
+        assert_eq!(
+            vec![".hidden.rs", "lib.rs"],
+            walk(&dir.path().join("src"), hidden)
+        );
+    }
+
+    #[test]
+    fn never_walks_into_the_index() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("src")).unwrap();
+        fs::write(dir.path().join(INDEX_FILE), "").unwrap();
+        fs::write(dir.path().join("src").join(INDEX_FILE), "").unwrap();
+        fs::write(dir.path().join("src/lib.rs"), "").unwrap();
+
+        let everything = WalkOptions {
+            no_ignore: true,
+            hidden: true,
+        };
+        assert_eq!(vec!["src/lib.rs"], walk(dir.path(), everything));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unreadable_directories_are_skipped() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("locked")).unwrap();
+        fs::create_dir_all(dir.path().join("open")).unwrap();
+        fs::write(dir.path().join("locked/secret.rs"), "").unwrap();
+        fs::write(dir.path().join("open/lib.rs"), "").unwrap();
+
+        let locked = dir.path().join("locked");
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o000)).unwrap();
+        // root reads every directory anyway
+        let readable = fs::read_dir(&locked).is_ok();
+
+        let root = dir.path().to_str().unwrap().to_string();
+        let walked = collect_files(&[root], &filter(&[]), WalkOptions::default());
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o755)).unwrap();
+
+        if !readable {
+            let (files, unreadable) = walked.unwrap();
+            let relative: Vec<_> = files
+                .iter()
+                .map(|file| file.path.strip_prefix(dir.path()).unwrap().to_path_buf())
+                .collect();
+
+            assert_eq!(vec![PathBuf::from("open/lib.rs")], relative);
+            assert_eq!(1, unreadable);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn links_to_directories_are_not_followed() {
+        use std::os::unix::fs::symlink;
+
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("sub")).unwrap();
+        fs::write(dir.path().join("sub/lib.rs"), "").unwrap();
+        symlink("..", dir.path().join("sub/up")).unwrap();
+        symlink("lib.rs", dir.path().join("sub/link.rs")).unwrap();
+
+        assert_eq!(
+            vec!["sub/lib.rs", "sub/link.rs"],
+            walk(dir.path(), WalkOptions::default())
+        );
+    }
+}